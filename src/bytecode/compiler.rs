@@ -0,0 +1,765 @@
+use crate::bytecode::chunk::{Chunk, OpCode};
+use crate::bytecode::heap::Heap;
+use crate::bytecode::object::{Obj, Function};
+use crate::bytecode::value::{ObjRef, Value};
+use crate::common::LoxError;
+use crate::common::expression::Expr;
+use crate::common::statement::Stmt;
+use crate::common::parser::MAX_ARGUMENTS;
+use crate::common::token::{Token, Literal, Span};
+use crate::common::token::token_type::TokenType;
+use std::rc::Rc;
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+const MAX_CONSTANTS: usize = 256;
+const MAX_LIST_ELEMENTS: usize = 255;
+const MAX_MAP_ENTRIES: usize = 255;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+// A local's depth is None between its declaration and the end of its initializer
+struct Local {
+    name: String,
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+struct Upvalue {
+    index: u8,
+    is_local: bool,
+}
+
+// Jumps emitted by `break` and `continue` that are patched once the end of the body and
+// of the loop are known
+struct Loop {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct FunctionState {
+    function_type: FunctionType,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+}
+
+struct ClassState {
+    has_superclass: bool,
+}
+
+struct Compiler<'h> {
+    heap: &'h mut Heap,
+    functions: Vec<FunctionState>,
+    classes: Vec<ClassState>,
+    span: Span,
+    is_repl: bool,
+}
+
+// Compiles a program into the function that runs its top-level code. In the REPL the
+// values of top-level expression statements are printed rather than discarded.
+pub fn compile(statements: &[Stmt], heap: &mut Heap, is_repl: bool) -> Result<ObjRef, LoxError> {
+    let mut compiler = Compiler {
+        heap,
+        functions: vec![FunctionState::new(FunctionType::Script)],
+        classes: Vec::new(),
+        span: Span::on_line(1),
+        is_repl,
+    };
+
+    for statement in statements {
+        compiler.statement(statement)?;
+    }
+    compiler.emit_return();
+
+    let state = compiler.functions.pop().unwrap();
+    Ok(compiler.finish_function(state, None))
+}
+
+impl FunctionState {
+    fn new(function_type: FunctionType) -> FunctionState {
+        // Slot zero holds the function being called, or the receiver inside methods
+        let receiver = match function_type {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            _ => "",
+        };
+        let locals = vec![Local { name: receiver.to_string(), depth: Some(0), is_captured: false }];
+
+        FunctionState {
+            function_type,
+            arity: 0,
+            chunk: Chunk::new(),
+            locals,
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+        }
+    }
+}
+
+impl<'h> Compiler<'h> {
+    fn statement(&mut self, statement: &Stmt) -> Result<(), LoxError> {
+        match statement {
+            Stmt::Expr(expr) => {
+                self.expression(expr)?;
+                let is_top_level = self.functions.len() == 1 && self.current().scope_depth == 0;
+                if self.is_repl && is_top_level && **expr != Expr::Empty {
+                    self.emit_op(OpCode::Print);
+                } else {
+                    self.emit_op(OpCode::Pop);
+                }
+            }
+            Stmt::Print(keyword, expr) => {
+                self.span = keyword.span();
+                self.expression(expr)?;
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Var(name, initializer) => {
+                self.span = name.span();
+                self.declare_variable(name)?;
+                self.expression(initializer)?;
+                self.define_variable(name)?;
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.end_scope();
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.statement(else_branch)?;
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::While(condition, body, increment) => {
+                let loop_start = self.current().chunk.code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+
+                let scope_depth = self.current().scope_depth;
+                self.current().loops.push(Loop { scope_depth, breaks: Vec::new(), continues: Vec::new() });
+                self.statement(body)?;
+
+                let continues = std::mem::take(&mut self.current().loops.last_mut().unwrap().continues);
+                for jump in continues {
+                    self.patch_jump(jump)?;
+                }
+                if **increment != Expr::Empty {
+                    self.expression(increment)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+
+                // A break has already popped the condition, so it lands after the Pop
+                let finished = self.current().loops.pop().unwrap();
+                for jump in finished.breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+            Stmt::ForIn(name, iterable, body) => self.for_in(name, iterable, body)?,
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => {
+                self.span = keyword.span();
+                let scope_depth = match self.current().loops.last() {
+                    Some(innermost) => innermost.scope_depth,
+                    None => {
+                        let word = if let Stmt::Break(_) = statement { "break" } else { "continue" };
+                        let message = format!("Can't use '{}' outside of a loop", word);
+                        return Err(self.error(&message));
+                    }
+                };
+                self.discard_locals(scope_depth);
+                let jump = self.emit_jump(OpCode::Jump);
+                let innermost = self.current().loops.last_mut().unwrap();
+                match statement {
+                    Stmt::Break(_) => innermost.breaks.push(jump),
+                    _ => innermost.continues.push(jump),
+                }
+            }
+            Stmt::Function(name, parameters, body) => {
+                self.span = name.span();
+                self.declare_variable(name)?;
+                // A function may refer to itself, so it is usable before its body is compiled
+                self.mark_initialized();
+                self.function(FunctionType::Function, &name.lexeme, parameters, body)?;
+                self.define_variable(name)?;
+            }
+            Stmt::Return(keyword, value) => {
+                self.span = keyword.span();
+                match self.current().function_type {
+                    FunctionType::Script => {
+                        return Err(self.error("Can't return from top-level code"));
+                    }
+                    FunctionType::Initializer if **value != Expr::Empty => {
+                        return Err(self.error("Can't return a value from an initializer"));
+                    }
+                    _ => {}
+                }
+
+                if **value == Expr::Empty {
+                    self.emit_return();
+                } else {
+                    self.expression(value)?;
+                    self.emit_op(OpCode::Return);
+                }
+            }
+            Stmt::Class(name, superclass, methods) => self.class(name, superclass, methods)?,
+        }
+        Ok(())
+    }
+
+    // The iterator lives in a hidden local for the whole loop. Each pass pushes the next
+    // value as the loop variable, in a scope of its own so closures capture that pass's
+    // value.
+    fn for_in(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> Result<(), LoxError> {
+        self.begin_scope();
+        self.expression(iterable)?;
+        self.span = name.span();
+        self.emit_op(OpCode::GetIterator);
+        self.add_local("(iterator)")?;
+        self.mark_initialized();
+        let iterator = (self.current().locals.len() - 1) as u8;
+
+        let loop_start = self.current().chunk.code.len();
+        self.emit_op(OpCode::GetLocal);
+        self.emit_byte(iterator);
+        self.emit_op(OpCode::HasNext);
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+
+        let scope_depth = self.current().scope_depth;
+        self.current().loops.push(Loop { scope_depth, breaks: Vec::new(), continues: Vec::new() });
+        self.begin_scope();
+        self.emit_op(OpCode::GetLocal);
+        self.emit_byte(iterator);
+        self.emit_op(OpCode::Next);
+        self.declare_variable(name)?;
+        self.mark_initialized();
+        self.statement(body)?;
+        self.end_scope();
+
+        // A continue has already dropped the loop variable
+        let continues = std::mem::take(&mut self.current().loops.last_mut().unwrap().continues);
+        for jump in continues {
+            self.patch_jump(jump)?;
+        }
+        self.emit_loop(loop_start)?;
+
+        self.patch_jump(exit_jump)?;
+        self.emit_op(OpCode::Pop);
+        let finished = self.current().loops.pop().unwrap();
+        for jump in finished.breaks {
+            self.patch_jump(jump)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn function(&mut self, function_type: FunctionType, name: &str, parameters: &[Token], body: &[Stmt]) -> Result<(), LoxError> {
+        self.functions.push(FunctionState::new(function_type));
+        self.begin_scope();
+
+        for parameter in parameters {
+            self.current().arity += 1;
+            if self.current().arity > MAX_ARGUMENTS {
+                return Err(self.error("Can't have more than 255 parameters"));
+            }
+            self.declare_variable(parameter)?;
+            self.define_variable(parameter)?;
+        }
+
+        for statement in body {
+            self.statement(statement)?;
+        }
+        self.emit_return();
+
+        let state = self.functions.pop().unwrap();
+        let upvalues: Vec<(u8, u8)> = state.upvalues.iter()
+            .map(|upvalue| (upvalue.is_local as u8, upvalue.index))
+            .collect();
+        let name = self.heap.intern(name);
+        let function = self.finish_function(state, Some(name));
+
+        let constant = self.make_constant(Value::Obj(function))?;
+        self.emit_op(OpCode::Closure);
+        self.emit_byte(constant);
+        for (is_local, index) in upvalues {
+            self.emit_byte(is_local);
+            self.emit_byte(index);
+        }
+        Ok(())
+    }
+
+    fn finish_function(&mut self, state: FunctionState, name: Option<ObjRef>) -> ObjRef {
+        let function = Function {
+            name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: Rc::new(state.chunk),
+        };
+        self.heap.alloc(Obj::Function(function))
+    }
+
+    fn class(&mut self, name: &Token, superclass: &Option<Box<Expr>>, methods: &[Stmt]) -> Result<(), LoxError> {
+        self.span = name.span();
+        let name_constant = self.identifier_constant(&name.lexeme)?;
+        self.declare_variable(name)?;
+        self.emit_op(OpCode::Class);
+        self.emit_byte(name_constant);
+        self.define_variable(name)?;
+
+        self.classes.push(ClassState { has_superclass: false });
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable(superclass_name, _) = &**superclass {
+                if superclass_name.lexeme == name.lexeme {
+                    return Err(self.error("A class can't inherit from itself"));
+                }
+            }
+
+            // The superclass stays on the stack as the local `super` while methods compile
+            self.expression(superclass)?;
+            self.begin_scope();
+            self.add_local("super")?;
+            self.mark_initialized();
+
+            self.named_variable(&name.lexeme, false)?;
+            self.emit_op(OpCode::Inherit);
+            self.classes.last_mut().unwrap().has_superclass = true;
+        }
+
+        self.named_variable(&name.lexeme, false)?;
+        for method in methods {
+            if let Stmt::Function(method_name, parameters, body) = method {
+                self.span = method_name.span();
+                let constant = self.identifier_constant(&method_name.lexeme)?;
+                let function_type = if method_name.lexeme == "init" {
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::Method
+                };
+                self.function(function_type, &method_name.lexeme, parameters, body)?;
+                self.emit_op(OpCode::Method);
+                self.emit_byte(constant);
+            }
+        }
+        self.emit_op(OpCode::Pop);
+
+        if self.classes.pop().unwrap().has_superclass {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Result<(), LoxError> {
+        match expression {
+            Expr::Literal(literal) => self.literal(literal)?,
+            Expr::Grouping(expr) => self.expression(expr)?,
+            Expr::Unary(operator, right) => {
+                self.expression(right)?;
+                self.span = operator.span();
+                match operator.type_of {
+                    TokenType::Minus => self.emit_op(OpCode::Negate),
+                    _ => self.emit_op(OpCode::Not),
+                }
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.span = operator.span();
+                match operator.type_of {
+                    TokenType::Plus => self.emit_op(OpCode::Add),
+                    TokenType::Minus => self.emit_op(OpCode::Subtract),
+                    TokenType::Star => self.emit_op(OpCode::Multiply),
+                    TokenType::Slash => self.emit_op(OpCode::Divide),
+                    TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+                    TokenType::BangEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+                    TokenType::Greater => self.emit_op(OpCode::Greater),
+                    TokenType::GreaterEqual => self.emit_ops(OpCode::Less, OpCode::Not),
+                    TokenType::Less => self.emit_op(OpCode::Less),
+                    TokenType::LessEqual => self.emit_ops(OpCode::Greater, OpCode::Not),
+                    _ => return Err(self.error("Could not match binary operator")),
+                }
+            }
+            Expr::Logical(left, operator, right) => {
+                self.expression(left)?;
+                self.span = operator.span();
+                if operator.type_of == TokenType::And {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            Expr::Variable(name, _) => {
+                self.span = name.span();
+                self.named_variable(&name.lexeme, false)?;
+            }
+            Expr::Assign(name, value, _) => {
+                self.expression(value)?;
+                self.span = name.span();
+                self.named_variable(&name.lexeme, true)?;
+            }
+            Expr::Call(callee, paren, arguments) => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.span = paren.span();
+                if arguments.len() > MAX_ARGUMENTS {
+                    return Err(self.error("Can't have more than 255 arguments"));
+                }
+                self.emit_op(OpCode::Call);
+                self.emit_byte(arguments.len() as u8);
+            }
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                self.span = name.span();
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.emit_op(OpCode::GetProperty);
+                self.emit_byte(constant);
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.span = name.span();
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.emit_op(OpCode::SetProperty);
+                self.emit_byte(constant);
+            }
+            Expr::This(keyword, _) => {
+                self.span = keyword.span();
+                if self.classes.is_empty() {
+                    return Err(self.error("Can't use 'this' outside of a class"));
+                }
+                self.named_variable("this", false)?;
+            }
+            Expr::Super(keyword, method, _) => {
+                self.span = keyword.span();
+                match self.classes.last() {
+                    None => return Err(self.error("Can't use 'super' outside of a class")),
+                    Some(class) if !class.has_superclass => {
+                        return Err(self.error("Can't use 'super' in a class with no superclass"));
+                    }
+                    _ => {}
+                }
+                let constant = self.identifier_constant(&method.lexeme)?;
+                self.named_variable("this", false)?;
+                self.named_variable("super", false)?;
+                self.emit_op(OpCode::GetSuper);
+                self.emit_byte(constant);
+            }
+            Expr::List(bracket, elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.span = bracket.span();
+                if elements.len() > MAX_LIST_ELEMENTS {
+                    return Err(self.error("Can't have more than 255 elements in a list literal"));
+                }
+                self.emit_op(OpCode::BuildList);
+                self.emit_byte(elements.len() as u8);
+            }
+            // Each key is followed by its value on the stack
+            Expr::Map(brace, entries) => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.span = brace.span();
+                if entries.len() > MAX_MAP_ENTRIES {
+                    return Err(self.error("Can't have more than 255 entries in a map literal"));
+                }
+                self.emit_op(OpCode::BuildMap);
+                self.emit_byte(entries.len() as u8);
+            }
+            Expr::Index(object, bracket, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.span = bracket.span();
+                self.emit_op(OpCode::GetIndex);
+            }
+            Expr::SetIndex(object, bracket, index, value) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.span = bracket.span();
+                self.emit_op(OpCode::SetIndex);
+            }
+            // A bound that was left out is Empty, which compiles to nil
+            Expr::Slice(object, bracket, start, end) => {
+                self.expression(object)?;
+                self.expression(start)?;
+                self.expression(end)?;
+                self.span = bracket.span();
+                self.emit_op(OpCode::Slice);
+            }
+            Expr::Lambda(keyword, parameters, body) => {
+                self.span = keyword.span();
+                self.function(FunctionType::Function, "lambda", parameters, body)?;
+            }
+            Expr::Empty => self.emit_op(OpCode::Nil),
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Result<(), LoxError> {
+        match literal {
+            Literal::Number(number) => self.emit_constant(Value::Number(*number))?,
+            Literal::String(string) => {
+                let string = self.heap.intern(string);
+                self.emit_constant(Value::Obj(string))?;
+            }
+            Literal::Bool(true) => self.emit_op(OpCode::True),
+            Literal::Bool(false) => self.emit_op(OpCode::False),
+            Literal::None | Literal::Nil => self.emit_op(OpCode::Nil),
+        }
+        Ok(())
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool) -> Result<(), LoxError> {
+        let innermost = self.functions.len() - 1;
+        let (get_op, set_op, operand) = if let Some(slot) = self.resolve_local(innermost, name)? {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(innermost, name)? {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            (OpCode::GetGlobal, OpCode::SetGlobal, self.identifier_constant(name)?)
+        };
+
+        self.emit_op(if assign { set_op } else { get_op });
+        self.emit_byte(operand);
+        Ok(())
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Result<Option<u8>, LoxError> {
+        let locals = &self.functions[function].locals;
+        match locals.iter().rposition(|local| local.name == name) {
+            Some(slot) if locals[slot].depth.is_none() => {
+                Err(self.error("Can't read local variable in its own initializer"))
+            }
+            Some(slot) => Ok(Some(slot as u8)),
+            None => Ok(None),
+        }
+    }
+
+    // Finds a variable declared in an enclosing function, capturing it in every function
+    // between there and here
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Result<Option<u8>, LoxError> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        if let Some(slot) = self.resolve_local(function - 1, name)? {
+            self.functions[function - 1].locals[slot as usize].is_captured = true;
+            return self.add_upvalue(function, slot, true).map(Some);
+        }
+
+        match self.resolve_upvalue(function - 1, name)? {
+            Some(index) => self.add_upvalue(function, index, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool) -> Result<u8, LoxError> {
+        let upvalues = &self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|upvalue| upvalue.index == index && upvalue.is_local == is_local) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() == MAX_UPVALUES {
+            return Err(self.error("Too many closure variables in function"));
+        }
+
+        self.functions[function].upvalues.push(Upvalue { index, is_local });
+        Ok((self.functions[function].upvalues.len() - 1) as u8)
+    }
+
+    fn declare_variable(&mut self, name: &Token) -> Result<(), LoxError> {
+        let scope_depth = self.current().scope_depth;
+        if scope_depth == 0 {
+            return Ok(());
+        }
+
+        let duplicate = self.current().locals.iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth >= scope_depth))
+            .any(|local| local.name == name.lexeme);
+        if duplicate {
+            return Err(self.error("Already a variable with this name in this scope"));
+        }
+
+        self.add_local(&name.lexeme)
+    }
+
+    fn define_variable(&mut self, name: &Token) -> Result<(), LoxError> {
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+            return Ok(());
+        }
+
+        let constant = self.identifier_constant(&name.lexeme)?;
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_byte(constant);
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &str) -> Result<(), LoxError> {
+        if self.current().locals.len() == MAX_LOCALS {
+            return Err(self.error("Too many local variables in function"));
+        }
+        let local = Local { name: name.to_string(), depth: None, is_captured: false };
+        self.current().locals.push(local);
+        Ok(())
+    }
+
+    fn mark_initialized(&mut self) {
+        let scope_depth = self.current().scope_depth;
+        if scope_depth == 0 {
+            return;
+        }
+        if let Some(local) = self.current().locals.last_mut() {
+            local.depth = Some(scope_depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        let scope_depth = self.current().scope_depth;
+        self.discard_locals(scope_depth);
+        self.current().locals.retain(|local| local.depth.is_some_and(|depth| depth <= scope_depth));
+    }
+
+    // Emits the instructions that drop every local deeper than scope_depth off the stack
+    fn discard_locals(&mut self, scope_depth: usize) {
+        let captured: Vec<bool> = self.current().locals.iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth > scope_depth))
+            .map(|local| local.is_captured)
+            .collect();
+
+        for is_captured in captured {
+            if is_captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> Result<u8, LoxError> {
+        let name = self.heap.intern(name);
+        self.make_constant(Value::Obj(name))
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u8, LoxError> {
+        if let Some(index) = self.current().chunk.constants.iter().position(|constant| *constant == value) {
+            return Ok(index as u8);
+        }
+        if self.current().chunk.constants.len() == MAX_CONSTANTS {
+            return Err(self.error("Too many constants in one chunk"));
+        }
+        Ok(self.current().chunk.add_constant(value) as u8)
+    }
+
+    fn emit_constant(&mut self, value: Value) -> Result<(), LoxError> {
+        let constant = self.make_constant(value)?;
+        self.emit_op(OpCode::Constant);
+        self.emit_byte(constant);
+        Ok(())
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().function_type == FunctionType::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    // Emits a jump with a placeholder offset, returning where the offset needs patching
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current().chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), LoxError> {
+        let jump = self.current().chunk.code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err(self.error("Too much code to jump over"));
+        }
+        let code = &mut self.current().chunk.code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), LoxError> {
+        self.emit_op(OpCode::Loop);
+        let offset = self.current().chunk.code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            return Err(self.error("Loop body too large"));
+        }
+        self.emit_byte((offset >> 8) as u8);
+        self.emit_byte(offset as u8);
+        Ok(())
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        let span = self.span;
+        self.current().chunk.write_op(op, span);
+    }
+
+    fn emit_ops(&mut self, first: OpCode, second: OpCode) {
+        self.emit_op(first);
+        self.emit_op(second);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let span = self.span;
+        self.current().chunk.write(byte, span);
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn error(&self, message: &str) -> LoxError {
+        LoxError::Compile(self.span, message.to_string())
+    }
+}
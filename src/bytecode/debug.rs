@@ -0,0 +1,109 @@
+use crate::bytecode::chunk::{Chunk, OpCode};
+use crate::bytecode::heap::Heap;
+use crate::bytecode::object::Obj;
+use crate::bytecode::value::{ObjRef, Value};
+use std::fmt::Write;
+
+// Lists a function's instructions, followed by those of every function nested inside it
+pub fn disassemble_function(heap: &Heap, function: ObjRef) -> String {
+    let function = heap.function(function);
+    let name = match function.name {
+        Some(name) => heap.string(name).to_string(),
+        None => "<script>".to_string(),
+    };
+
+    let mut output = disassemble_chunk(heap, &function.chunk, &name);
+    for constant in &function.chunk.constants {
+        if let Value::Obj(reference) = constant {
+            if let Obj::Function(_) = heap.get(*reference) {
+                output.push('\n');
+                output.push_str(&disassemble_function(heap, *reference));
+            }
+        }
+    }
+    output
+}
+
+pub fn disassemble_chunk(heap: &Heap, chunk: &Chunk, name: &str) -> String {
+    let mut output = format!("== {} ==\n", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(heap, chunk, offset, &mut output);
+    }
+    output
+}
+
+// Appends one instruction to the output, returning the offset of the next one. The line
+// column shows `|` when an instruction comes from the same line as the one before it.
+pub fn disassemble_instruction(heap: &Heap, chunk: &Chunk, offset: usize, output: &mut String) -> usize {
+    write!(output, "{:04} ", offset).unwrap();
+    let line = chunk.spans[offset].line;
+    if offset > 0 && line == chunk.spans[offset - 1].line {
+        output.push_str("   | ");
+    } else {
+        write!(output, "{:4} ", line).unwrap();
+    }
+
+    let op = match OpCode::from_byte(chunk.code[offset]) {
+        Some(op) => op,
+        None => {
+            writeln!(output, "Unknown opcode {}", chunk.code[offset]).unwrap();
+            return offset + 1;
+        }
+    };
+
+    match op {
+        OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal |
+        OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper | OpCode::Class |
+        OpCode::Method => constant_instruction(heap, chunk, op, offset, output),
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue |
+        OpCode::Call | OpCode::BuildList | OpCode::BuildMap => byte_instruction(chunk, op, offset, output),
+        OpCode::Jump | OpCode::JumpIfFalse => jump_instruction(chunk, op, true, offset, output),
+        OpCode::Loop => jump_instruction(chunk, op, false, offset, output),
+        OpCode::Closure => closure_instruction(heap, chunk, offset, output),
+        _ => {
+            writeln!(output, "{:?}", op).unwrap();
+            offset + 1
+        }
+    }
+}
+
+fn constant_instruction(heap: &Heap, chunk: &Chunk, op: OpCode, offset: usize, output: &mut String) -> usize {
+    let constant = chunk.code[offset + 1];
+    let value = heap.format(chunk.constants[constant as usize]);
+    writeln!(output, "{:<16} {:4} {}", format!("{:?}", op), constant, value).unwrap();
+    offset + 2
+}
+
+fn byte_instruction(chunk: &Chunk, op: OpCode, offset: usize, output: &mut String) -> usize {
+    let slot = chunk.code[offset + 1];
+    writeln!(output, "{:<16} {:4}", format!("{:?}", op), slot).unwrap();
+    offset + 2
+}
+
+fn jump_instruction(chunk: &Chunk, op: OpCode, forward: bool, offset: usize, output: &mut String) -> usize {
+    let jump = chunk.read_short(offset + 1) as usize;
+    let next = offset + 3;
+    let target = if forward { next + jump } else { next - jump };
+    writeln!(output, "{:<16} {:4} -> {}", format!("{:?}", op), offset, target).unwrap();
+    next
+}
+
+// Closures are followed by a pair of bytes for each variable they capture
+fn closure_instruction(heap: &Heap, chunk: &Chunk, offset: usize, output: &mut String) -> usize {
+    let constant = chunk.code[offset + 1];
+    let function = match chunk.constants[constant as usize] {
+        Value::Obj(reference) => reference,
+        _ => unreachable!("Expected a function"),
+    };
+    writeln!(output, "{:<16} {:4} {}", "Closure", constant, heap.format(Value::Obj(function))).unwrap();
+
+    let mut offset = offset + 2;
+    for _ in 0..heap.function(function).upvalue_count {
+        let kind = if chunk.code[offset] == 1 { "local" } else { "upvalue" };
+        let index = chunk.code[offset + 1];
+        writeln!(output, "{:04}    |                     {} {}", offset, kind, index).unwrap();
+        offset += 2;
+    }
+    offset
+}
@@ -0,0 +1,327 @@
+use crate::bytecode::object::{Obj, Function, Closure, Upvalue, Class, Instance};
+use crate::bytecode::value::{ObjRef, Value};
+use crate::common::map::{self, Key, Map};
+use std::collections::HashMap;
+
+const INITIAL_GC_THRESHOLD: usize = 1024;
+const DEFAULT_GROWTH_FACTOR: usize = 2;
+
+// Owns every object created while compiling and running a program. Strings are interned
+// so that equal strings share one handle. Freed slots are reused by later allocations.
+pub struct Heap {
+    objects: Vec<Option<Obj>>,
+    marks: Vec<bool>,
+    free: Vec<usize>,
+    strings: HashMap<String, ObjRef>,
+    live: usize,
+    next_gc: usize,
+    growth_factor: usize,
+    stress: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap {
+            objects: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            strings: HashMap::new(),
+            live: 0,
+            next_gc: INITIAL_GC_THRESHOLD,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            stress: false,
+        }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap::default()
+    }
+
+    pub fn alloc(&mut self, object: Obj) -> ObjRef {
+        self.live += 1;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+
+    pub fn intern(&mut self, string: &str) -> ObjRef {
+        if let Some(reference) = self.strings.get(string) {
+            return *reference;
+        }
+        let reference = self.alloc(Obj::String(string.to_string()));
+        self.strings.insert(string.to_string(), reference);
+        reference
+    }
+
+    // Looks up an interned string without creating it
+    pub fn find_string(&self, string: &str) -> Option<ObjRef> {
+        self.strings.get(string).copied()
+    }
+
+    pub fn get(&self, reference: ObjRef) -> &Obj {
+        self.objects[reference.0].as_ref().expect("Use of a collected object")
+    }
+
+    pub fn get_mut(&mut self, reference: ObjRef) -> &mut Obj {
+        self.objects[reference.0].as_mut().expect("Use of a collected object")
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.live
+    }
+
+    // After a collection the next one is due once the live count has grown by this factor
+    pub fn set_growth_factor(&mut self, growth_factor: usize) {
+        self.growth_factor = growth_factor.max(1);
+    }
+
+    // Under stress every allocation made by the VM is preceded by a full collection
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.live >= self.next_gc
+    }
+
+    // Marks everything reachable from the roots, then frees the rest. Returns the number
+    // of objects freed.
+    pub fn collect(&mut self, roots: &[ObjRef]) -> usize {
+        let mut gray = Vec::new();
+        for root in roots {
+            self.mark(*root, &mut gray);
+        }
+        while let Some(reference) = gray.pop() {
+            self.blacken(reference, &mut gray);
+        }
+
+        let freed = self.sweep();
+        self.next_gc = (self.live * self.growth_factor).max(INITIAL_GC_THRESHOLD);
+        freed
+    }
+
+    fn mark(&mut self, reference: ObjRef, gray: &mut Vec<ObjRef>) {
+        if !self.marks[reference.0] {
+            self.marks[reference.0] = true;
+            gray.push(reference);
+        }
+    }
+
+    fn mark_value(&mut self, value: Value, gray: &mut Vec<ObjRef>) {
+        if let Value::Obj(reference) = value {
+            self.mark(reference, gray);
+        }
+    }
+
+    fn blacken(&mut self, reference: ObjRef, gray: &mut Vec<ObjRef>) {
+        let mut children = Vec::new();
+        match self.get(reference) {
+            Obj::String(_) | Obj::Native(_) => {}
+            Obj::Function(function) => {
+                children.extend(function.name.map(Value::Obj));
+                children.extend(function.chunk.constants.iter().copied());
+            }
+            Obj::Closure(closure) => {
+                children.push(Value::Obj(closure.function));
+                children.extend(closure.upvalues.iter().map(|upvalue| Value::Obj(*upvalue)));
+            }
+            Obj::Upvalue(Upvalue::Open(_)) => {}
+            Obj::Upvalue(Upvalue::Closed(value)) => children.push(*value),
+            Obj::Class(class) => {
+                children.push(Value::Obj(class.name));
+                for (name, method) in &class.methods {
+                    children.push(Value::Obj(*name));
+                    children.push(Value::Obj(*method));
+                }
+            }
+            Obj::Instance(instance) => {
+                children.push(Value::Obj(instance.class));
+                for (name, value) in &instance.fields {
+                    children.push(Value::Obj(*name));
+                    children.push(*value);
+                }
+            }
+            Obj::BoundMethod(bound_method) => {
+                children.push(bound_method.receiver);
+                children.push(Value::Obj(bound_method.method));
+            }
+            Obj::List(elements) => children.extend(elements.iter().copied()),
+            Obj::Cursor(cursor) => children.push(Value::Obj(cursor.source)),
+            Obj::Map(entries) => {
+                for (key, value) in entries.iter() {
+                    children.push(Value::from(key));
+                    children.push(*value);
+                }
+            }
+        }
+
+        for child in children {
+            self.mark_value(child, gray);
+        }
+    }
+
+    // The string table doesn't keep strings alive, so unmarked strings leave it here too
+    fn sweep(&mut self) -> usize {
+        let marks = &self.marks;
+        self.strings.retain(|_, reference| marks[reference.0]);
+
+        let mut freed = 0;
+        for index in 0..self.objects.len() {
+            if self.marks[index] {
+                self.marks[index] = false;
+            } else if self.objects[index].take().is_some() {
+                self.free.push(index);
+                freed += 1;
+            }
+        }
+        self.live -= freed;
+        freed
+    }
+
+    pub fn string(&self, reference: ObjRef) -> &str {
+        match self.get(reference) {
+            Obj::String(string) => string,
+            _ => unreachable!("Expected a string"),
+        }
+    }
+
+    pub fn function(&self, reference: ObjRef) -> &Function {
+        match self.get(reference) {
+            Obj::Function(function) => function,
+            _ => unreachable!("Expected a function"),
+        }
+    }
+
+    pub fn closure(&self, reference: ObjRef) -> &Closure {
+        match self.get(reference) {
+            Obj::Closure(closure) => closure,
+            _ => unreachable!("Expected a closure"),
+        }
+    }
+
+    pub fn upvalue(&self, reference: ObjRef) -> &Upvalue {
+        match self.get(reference) {
+            Obj::Upvalue(upvalue) => upvalue,
+            _ => unreachable!("Expected an upvalue"),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, reference: ObjRef) -> &mut Upvalue {
+        match self.get_mut(reference) {
+            Obj::Upvalue(upvalue) => upvalue,
+            _ => unreachable!("Expected an upvalue"),
+        }
+    }
+
+    pub fn class(&self, reference: ObjRef) -> &Class {
+        match self.get(reference) {
+            Obj::Class(class) => class,
+            _ => unreachable!("Expected a class"),
+        }
+    }
+
+    pub fn class_mut(&mut self, reference: ObjRef) -> &mut Class {
+        match self.get_mut(reference) {
+            Obj::Class(class) => class,
+            _ => unreachable!("Expected a class"),
+        }
+    }
+
+    pub fn list(&self, reference: ObjRef) -> &Vec<Value> {
+        match self.get(reference) {
+            Obj::List(elements) => elements,
+            _ => unreachable!("Expected a list"),
+        }
+    }
+
+    pub fn list_mut(&mut self, reference: ObjRef) -> &mut Vec<Value> {
+        match self.get_mut(reference) {
+            Obj::List(elements) => elements,
+            _ => unreachable!("Expected a list"),
+        }
+    }
+
+    pub fn map(&self, reference: ObjRef) -> &Map<Key<ObjRef>, Value> {
+        match self.get(reference) {
+            Obj::Map(entries) => entries,
+            _ => unreachable!("Expected a map"),
+        }
+    }
+
+    pub fn map_mut(&mut self, reference: ObjRef) -> &mut Map<Key<ObjRef>, Value> {
+        match self.get_mut(reference) {
+            Obj::Map(entries) => entries,
+            _ => unreachable!("Expected a map"),
+        }
+    }
+
+    // The map key for a value, if it can be one
+    pub fn key(&self, value: Value) -> Result<Key<ObjRef>, String> {
+        match value {
+            Value::Nil => Ok(Key::Nil),
+            Value::Bool(value) => Ok(Key::Bool(value)),
+            Value::Number(number) => Key::number(number),
+            Value::Obj(reference) => match self.get(reference) {
+                Obj::String(_) => Ok(Key::String(reference)),
+                _ => Err(map::KEY_ERROR.to_string()),
+            },
+        }
+    }
+
+    pub fn instance_mut(&mut self, reference: ObjRef) -> &mut Instance {
+        match self.get_mut(reference) {
+            Obj::Instance(instance) => instance,
+            _ => unreachable!("Expected an instance"),
+        }
+    }
+
+    // Matches the tree-walk interpreter's formatting of printed values
+    pub fn format(&self, value: Value) -> String {
+        match value {
+            Value::Nil => "nil".to_string(),
+            Value::Bool(value) => format!("{}", value),
+            Value::Number(number) => format!("{}", number),
+            Value::Obj(reference) => self.format_object(reference),
+        }
+    }
+
+    fn format_object(&self, reference: ObjRef) -> String {
+        match self.get(reference) {
+            Obj::String(string) => format!("\"{}\"", string),
+            Obj::Function(function) => match function.name {
+                Some(name) => format!("<fn {}>", self.string(name)),
+                None => "<script>".to_string(),
+            },
+            Obj::Closure(closure) => self.format_object(closure.function),
+            Obj::Upvalue(_) => "upvalue".to_string(),
+            Obj::Class(class) => self.string(class.name).to_string(),
+            Obj::Instance(instance) => {
+                let class = self.class(instance.class);
+                format!("{} instance", self.string(class.name))
+            }
+            Obj::BoundMethod(bound_method) => self.format_object(bound_method.method),
+            Obj::Native(native) => format!("<native fn {}>", native.name),
+            Obj::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| self.format(*element)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Obj::Map(entries) => {
+                let entries: Vec<String> = entries.iter()
+                    .map(|(key, value)| format!("{}: {}", self.format(Value::from(key)), self.format(*value)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Obj::Cursor(_) => "<iterator>".to_string(),
+        }
+    }
+}
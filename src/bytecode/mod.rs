@@ -0,0 +1,527 @@
+mod chunk;
+mod value;
+mod object;
+mod heap;
+mod compiler;
+mod vm;
+mod debug;
+mod native;
+
+pub use self::vm::VM;
+
+use crate::bytecode::heap::Heap;
+use crate::common::LoxError;
+use crate::common::{parser, resolver};
+use crate::common::scanner::Scanner;
+use crate::repl::{self, Session};
+use std::fs;
+
+
+// The VM is shared between lines so that globals survive from one line to the next
+pub fn run_prompt(mut vm: VM) {
+    repl::run_prompt(&mut vm);
+}
+
+pub fn run_file(path: &str, mut vm: VM) {
+    let contents = fs::read_to_string(path)
+        .expect("Couldn't open file!");
+
+    if let Err(errors) = run(&mut vm, &contents, false) {
+        repl::report(&errors);
+    }
+}
+
+// Prints the bytecode compiled from a file instead of running it
+pub fn disassemble_file(path: &str) {
+    let contents = fs::read_to_string(path)
+        .expect("Couldn't open file!");
+
+    match disassemble(&contents) {
+        Ok(listing) => print!("{}", listing),
+        Err(errors) => repl::report(&errors),
+    }
+}
+
+// Compiles source and lists the instructions of the script and every function in it
+pub fn disassemble(source: &str) -> Result<String, Vec<LoxError>> {
+    let scanner = Scanner::new(source);
+    let mut tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
+    let mut statements = parser::parse(&mut tokens)?;
+    resolver::resolve(&mut statements)?;
+
+    let mut heap = Heap::new();
+    let script = compiler::compile(&statements, &mut heap, false).map_err(|error| vec![error])?;
+    Ok(debug::disassemble_function(&heap, script))
+}
+
+// Shares the front end with the tree-walk interpreter, then compiles to bytecode instead
+// of walking the tree
+fn run(vm: &mut VM, source: &str, is_repl: bool) -> Result<(), Vec<LoxError>> {
+    let scanner = Scanner::new(source);
+    let mut tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
+    let mut statements = parser::parse(&mut tokens)?;
+    resolver::resolve(&mut statements)?;
+
+    vm.interpret(&statements, is_repl).map_err(|error| vec![error])
+}
+
+impl Session for VM {
+    fn run(&mut self, source: &str, is_repl: bool) -> Result<(), Vec<LoxError>> {
+        run(self, source, is_repl)
+    }
+
+    fn bindings(&self) -> Vec<(String, String)> {
+        self.globals().into_iter()
+            .map(|(name, value)| (name, self.format(value)))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        VM::reset(self);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::VM;
+    use crate::bytecode::value::Value;
+    use crate::common::LoxError;
+    use crate::common::output::SharedBuffer;
+    use crate::common::token::Span;
+
+    // Collects on every allocation, so any test can catch an object the VM forgot to root
+    fn interpret_source(source: &str) -> Result<VM, LoxError> {
+        let mut vm = VM::new();
+        vm.set_gc_stress(true);
+        super::run(&mut vm, source, false).map_err(|mut errors| errors.remove(0))?;
+        Ok(vm)
+    }
+
+    fn span(line: u32, column: u32, start: usize, end: usize) -> Span {
+        Span { line, column, start, end }
+    }
+
+    fn printed(source: &str) -> String {
+        let buffer = SharedBuffer::default();
+        let mut vm = VM::new();
+        vm.set_gc_stress(true);
+        vm.set_output(Box::new(buffer.clone()));
+        super::run(&mut vm, source, false).unwrap();
+        buffer.contents()
+    }
+
+    fn global(vm: &VM, name: &str) -> String {
+        vm.format(vm.global(name).expect("Undefined global"))
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let vm = interpret_source("var result = (1 + 2) * 3 - 4 / 2;").unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(7.0)));
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        let vm = interpret_source("var a = \"he\"; var result = a + \"ya\";").unwrap();
+        assert_eq!(global(&vm, "result"), "\"heya\"");
+    }
+
+    #[test]
+    fn test_locals_and_blocks() {
+        let input = "var result = 0;
+                     { var a = 1; { var b = 2; result = a + b; } }";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let input = "var result = 0;
+                     for (var i = 0; i < 10; i = i + 1) {
+                         if (i == 5) break;
+                         result = result + i;
+                     }";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(10.0)));
+    }
+
+    #[test]
+    fn test_continue_and_nested_break() {
+        let input = "var sum = 0;
+                     for (var i = 0; i < 10; i = i + 1) {
+                         var skip = i == 2 or i == 5;
+                         { if (skip) { continue; } }
+                         for (var j = 0; j < 10; j = j + 1) { if (j == 1) { break; } }
+                         sum = sum + i;
+                     }";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("sum"), Some(Value::Number(38.0)));
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let input = "fun apply(f, x) { return f(x); }
+                     var offset = 10;
+                     var shifted = apply(fun (n) { return n + offset; }, 1);
+                     var curried = (x) => (y) => x * y;
+                     var product = curried(6)(7);
+                     var lambda = (x) => x;";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("shifted"), Some(Value::Number(11.0)));
+        assert_eq!(vm.global("product"), Some(Value::Number(42.0)));
+        assert_eq!(global(&vm, "lambda"), "<fn lambda>");
+    }
+
+    #[test]
+    fn test_lists() {
+        let input = "var xs = [1, \"two\", [3]];
+                     var alias = xs;
+                     xs[1] = \"deux\";
+                     var nested = xs[-1][0];
+                     push(xs, 4);
+                     insert(xs, 0, 0);
+                     var removed = remove(xs, -1);
+                     var popped = pop(xs);
+                     var slice = alias[1:];
+                     var length = len(xs);";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(global(&vm, "xs"), "[0, 1, \"deux\"]");
+        assert_eq!(global(&vm, "removed"), "4");
+        assert_eq!(global(&vm, "popped"), "[3]");
+        assert_eq!(vm.global("nested"), Some(Value::Number(3.0)));
+        assert_eq!(global(&vm, "slice"), "[1, \"deux\"]");
+        assert_eq!(vm.global("length"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let error = interpret_source("var xs = [1];\nxs[1];").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(2, 3, 16, 17), "Index 1 out of bounds for list of length 1".to_string()));
+        let error = interpret_source("var xs = [1]; xs[0:\"a\"];").err().unwrap();
+        assert_eq!(error.message(), "Slice bounds must be numbers");
+        let error = interpret_source("pop([]);").err().unwrap();
+        assert_eq!(error.message(), "Can't pop from an empty list");
+    }
+
+    #[test]
+    fn test_maps() {
+        let input = "var m = {\"a\": 1, 2: \"two\", nil: [3]};
+                     var alias = m;
+                     m[\"a\"] = 5;
+                     m[-0] = \"zero\";
+                     var nested = m[nil][0];
+                     var removed = delete(m, 2);
+                     var ks = keys(m);
+                     var vs = values(m);
+                     var found = has(alias, 0) and !has(alias, 2);
+                     var length = len(m);";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(global(&vm, "alias"), "{\"a\": 5, nil: [3], 0: \"zero\"}");
+        assert_eq!(vm.global("nested"), Some(Value::Number(3.0)));
+        assert_eq!(global(&vm, "removed"), "\"two\"");
+        assert_eq!(global(&vm, "ks"), "[\"a\", nil, 0]");
+        assert_eq!(global(&vm, "vs"), "[5, [3], \"zero\"]");
+        assert_eq!(vm.global("found"), Some(Value::Bool(true)));
+        assert_eq!(vm.global("length"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let error = interpret_source("var m = {\"a\": 1};\nm[\"b\"];").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(2, 2, 19, 20), "Undefined key \"b\"".to_string()));
+        let error = interpret_source("var m = {[]: 1};").err().unwrap();
+        assert_eq!(error.message(), "Map keys must be nil, booleans, numbers or strings");
+        let error = interpret_source("var m = {}; m[0:1];").err().unwrap();
+        assert_eq!(error.message(), "Only lists can be sliced");
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let input = "var seen = [];
+                     for (var x in [1, 2]) push(seen, x);
+                     for (var k in {\"a\": 1, 2: nil}) push(seen, k);
+                     for (var c in \"h\u{e9}!\") push(seen, c);
+                     for (var n in range(3, 6)) { if (n == 4) continue; push(seen, n); }
+                     var grown = [1];
+                     for (var x in grown) if (x < 3) push(grown, x + 1);
+                     var closures = [];
+                     for (var i in range(0, 3)) push(closures, fun () { return i; });
+                     var captured = closures[0]() + closures[2]();
+                     fun first(xs) { for (var x in xs) { return x; } return nil; }
+                     var found = first([\"only\"]);";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(global(&vm, "seen"), "[1, 2, \"a\", 2, \"h\", \"\u{e9}\", \"!\", 3, 5]");
+        assert_eq!(global(&vm, "grown"), "[1, 2, 3]");
+        assert_eq!(vm.global("captured"), Some(Value::Number(2.0)));
+        assert_eq!(global(&vm, "found"), "\"only\"");
+    }
+
+    #[test]
+    fn test_for_in_iterator_protocol() {
+        let input = "class Countdown {
+                         init(from) { this.from = from; }
+                         iterator() { return CountdownIterator(this.from); }
+                     }
+                     class CountdownIterator {
+                         init(n) { this.n = n; }
+                         hasNext() { return this.n > 0; }
+                         next() { this.n = this.n - 1; return this.n + 1; }
+                     }
+                     var total = 0;
+                     for (var n in Countdown(4)) {
+                         if (n == 1) break;
+                         total = total * 10 + n;
+                     }";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("total"), Some(Value::Number(432.0)));
+        let error = interpret_source("for (var x in 1) print x;").err().unwrap();
+        assert_eq!(error.message(), "Can only iterate over lists, maps, strings and iterators");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let input = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+                     var result = fib(10);";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(55.0)));
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let input = "fun makeCounter() { var i = 0; fun count() { i = i + 1; return i; } return count; }
+                     var counter = makeCounter();
+                     counter();
+                     var result = counter();";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_closures_share_captured_variable() {
+        let input = "var fInner; var gInner;
+                     { var a = 1; fun f() { a = a + 1; } fun g() { return a; } fInner = f; gInner = g; }
+                     fInner();
+                     var result = gInner();";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_class_initializer_and_method() {
+        let input = "class Foo { init(x) { this.x = x; } bar() { return this.x + 1; } }
+                     var foo = Foo(1);
+                     var result = foo.bar();";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(vm.global("result"), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_super_call() {
+        let input = "class A { name() { return \"A\"; } }
+                     class B < A { name() { return \"B\" + super.name(); } }
+                     var result = B().name();";
+        let vm = interpret_source(input).unwrap();
+        assert_eq!(global(&vm, "result"), "\"BA\"");
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let error = interpret_source("print x;").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(1, 7, 6, 7), "Undefined variable 'x'".to_string()));
+    }
+
+    #[test]
+    fn test_runtime_error_line() {
+        let error = interpret_source("var a = 1;\nvar b = a + \"x\";").err().unwrap();
+        assert_eq!(error.line(), 2);
+    }
+
+    #[test]
+    fn test_leading_whitespace_keeps_spans() {
+        let errors = super::run(&mut VM::new(), "  \n  print x;", false).err().unwrap();
+        assert_eq!(errors[0].span(), span(2, 9, 11, 12));
+        assert!(errors[0].to_string().starts_with("Line: 2:9 Runtime Error: "));
+    }
+
+    #[test]
+    fn test_leading_blank_lines_count() {
+        let errors = super::run(&mut VM::new(), "\n\nprint x;", false).err().unwrap();
+        assert_eq!(errors[0].line(), 3);
+        let listing = super::disassemble("\n\nprint 1;").unwrap();
+        assert!(listing.contains("0000    3 Constant"), "{}", listing);
+    }
+
+    #[test]
+    fn test_this_outside_class() {
+        let error = interpret_source("print this;").err().unwrap();
+        assert_eq!(error, LoxError::Resolve(span(1, 7, 6, 10), "at 'this': Can't use 'this' outside of a class".to_string()));
+    }
+
+    #[test]
+    fn test_globals_persist_between_runs() {
+        let mut vm = VM::new();
+        super::run(&mut vm, "var a = 1;", true).unwrap();
+        super::run(&mut vm, "a = a + 1;", true).unwrap();
+        assert_eq!(vm.global("a"), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_gc_stress_keeps_reachable_objects() {
+        let input = "class Node { init(value, next) { this.value = value; this.next = next; } }
+                     fun makeAdder(n) { fun add(x) { return x + n; } return add; }
+                     var list = nil;
+                     for (var i = 0; i < 20; i = i + 1) { list = Node(\"item\" + \"s\", list); }
+                     var add = makeAdder(2);
+                     var result = list.value + \"!\";
+                     var sum = add(3);";
+        let mut vm = VM::new();
+        vm.set_gc_stress(true);
+        super::run(&mut vm, input, false).unwrap();
+        assert_eq!(global(&vm, "result"), "\"items!\"");
+        assert_eq!(vm.global("sum"), Some(Value::Number(5.0)));
+    }
+
+    #[test]
+    fn test_gc_frees_unreachable_objects() {
+        let mut vm = VM::new();
+        super::run(&mut vm, "var kept = \"a\" + \"b\";", true).unwrap();
+        super::run(&mut vm, "{ var dropped = \"c\" + \"d\"; fun f() {} }", true).unwrap();
+        let before = vm.live_objects();
+
+        assert!(vm.collect_garbage() > 0);
+        assert!(vm.live_objects() < before);
+        assert_eq!(global(&vm, "kept"), "\"ab\"");
+        super::run(&mut vm, "kept = kept + \"c\";", true).unwrap();
+        assert_eq!(global(&vm, "kept"), "\"abc\"");
+    }
+
+    #[test]
+    fn test_gc_growth_factor_sets_how_often_to_collect() {
+        // Keeps 1100 instances alive, just past the first collection, then makes 300 that
+        // are garbage straight away
+        let input = "class Node {}
+                     var kept = [];
+                     for (var i = 0; i < 1100; i = i + 1) push(kept, Node());
+                     for (var i = 0; i < 300; i = i + 1) Node();";
+        let live_after = |growth_factor: usize| {
+            let mut vm = VM::new();
+            vm.set_gc_growth_factor(growth_factor);
+            super::run(&mut vm, input, false).unwrap();
+            vm.live_objects()
+        };
+
+        // A factor of 1 collects whenever the heap grows, so little garbage is left over.
+        // With 10 the first collection puts the next one beyond everything the program makes.
+        let eager = live_after(1);
+        let lazy = live_after(10);
+        assert!(eager < 1200, "{} objects live", eager);
+        assert!(lazy > 1350, "{} objects live", lazy);
+    }
+
+    #[test]
+    fn test_disassemble_constants_and_globals() {
+        let listing = super::disassemble("var a = 1;\nprint a;").unwrap();
+        let expected = "== <script> ==
+0000    1 Constant            0 1
+0002    | DefineGlobal        1 \"a\"
+0004    2 GetGlobal           1 \"a\"
+0006    | Print
+0007    | Nil
+0008    | Return
+";
+        assert_eq!(listing, expected);
+    }
+
+    #[test]
+    fn test_disassemble_jumps_and_functions() {
+        let listing = super::disassemble("fun f(x) { while (x) x = false; }").unwrap();
+        assert!(listing.contains("== f =="));
+        assert!(listing.contains("Closure             0 <fn f>"));
+        assert!(listing.contains("GetLocal            1"));
+        assert!(listing.contains("JumpIfFalse         2 -> 13"));
+        assert!(listing.contains("Loop               10 -> 0"));
+    }
+
+    #[test]
+    fn test_clock_native() {
+        let vm = interpret_source("var start = clock(); var elapsed = clock() - start;").unwrap();
+        assert!(matches!(vm.global("elapsed"), Some(Value::Number(seconds)) if seconds >= 0.0));
+        assert_eq!(global(&vm, "clock"), "<native fn clock>");
+        assert!(interpret_source("clock(1);").is_err());
+    }
+
+    #[test]
+    fn test_returned_nil_can_be_stored() {
+        assert_eq!(printed("fun f() { return nil; } var r = f(); print r;"), "nil\n");
+        assert_eq!(printed("fun g() { return; } var r = g(); print r;"), "nil\n");
+        assert_eq!(printed("class A { m() { return nil; } } var r = A().m(); print r;"), "nil\n");
+    }
+
+    #[test]
+    fn test_assignment_is_an_expression() {
+        assert_eq!(printed("var a; var b = a = 3; print b; print a = nil;"), "3\nnil\n");
+    }
+
+    #[test]
+    fn test_nil_in_lists_and_maps() {
+        assert_eq!(printed("var xs = [nil, 1]; print xs; print xs[0];"), "[nil, 1]\nnil\n");
+        assert_eq!(printed("var xs = [1]; xs[0] = nil; push(xs, nil); insert(xs, 0, nil); print xs;"), "[nil, nil, nil]\n");
+        assert_eq!(printed("var m = {\"a\": nil}; m[\"b\"] = nil; print m; print values(m);"), "{\"a\": nil, \"b\": nil}\n[nil, nil]\n");
+    }
+
+    #[test]
+    fn test_nil_in_variables() {
+        assert_eq!(printed("var x = nil; print x; var y; print y == x; x = 1; x = nil; print x;"), "nil\ntrue\nnil\n");
+        assert_eq!(printed("fun f(a) { var b = a; return b; } print f(nil);"), "nil\n");
+        assert_eq!(printed("var x = nil; print !x; if (x) print 1; else print 2;"), "true\n2\n");
+    }
+
+    #[test]
+    fn test_print_goes_to_output() {
+        let buffer = SharedBuffer::default();
+        let mut vm = VM::new();
+        vm.set_output(Box::new(buffer.clone()));
+
+        super::run(&mut vm, "fun greet(name) { print \"hi \" + name; } greet(\"lox\"); print 1 + 2;", false).unwrap();
+        super::run(&mut vm, "true;", true).unwrap();
+        assert_eq!(buffer.contents(), "\"hi lox\"\n3\ntrue\n");
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn test_trace_shows_stack_and_instructions() {
+        let buffer = SharedBuffer::default();
+        let mut vm = VM::new();
+        vm.set_trace(Box::new(buffer.clone()));
+        super::run(&mut vm, "var a = 1 + 2;", false).unwrap();
+
+        let trace = buffer.contents();
+        assert!(trace.contains("[trace] [<script>, 1, 2] 0004    | Add"), "{}", trace);
+        assert!(trace.contains("[trace] [<script>, 3] 0005    | DefineGlobal"), "{}", trace);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let error = interpret_source("fun f() { f(); } f();").err().unwrap();
+        assert_eq!(error.message(), "Stack overflow");
+    }
+
+    #[test]
+    fn test_truthiness_and_equality_match_treewalk() {
+        let samples = ["nil", "true", "false", "0", "1", "\"\"", "\"lox\"", "f", "g", "clock", "A", "B", "a", "b", "m", "xs", "ys", "ms"];
+        let mut vm = interpret_source("fun f() {} fun g(x) { return x; } class A { m() {} } class B {} \
+                                       var a = A(); var b = B(); var m = a.m; var xs = []; var ys = [1]; var ms = {};").unwrap();
+
+        for (i, left) in samples.iter().enumerate() {
+            let truthy = !matches!(*left, "nil" | "false");
+            super::run(&mut vm, &format!("var r = \"else\"; if ({}) r = \"then\";", left), false).unwrap();
+            assert_eq!(global(&vm, "r"), if truthy { "\"then\"" } else { "\"else\"" }, "if ({})", left);
+
+            for (j, right) in samples.iter().enumerate() {
+                super::run(&mut vm, &format!("var r = {} == {};", left, right), false).unwrap();
+                assert_eq!(vm.global("r"), Some(Value::Bool(i == j)), "{} == {}", left, right);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::bytecode::heap::Heap;
+use crate::bytecode::object::Obj;
+use crate::bytecode::value::{ObjRef, Value};
+use crate::common::list;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Seconds since the Unix epoch
+pub fn clock(_heap: &mut Heap, _arguments: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+// The number of elements in a list, of entries in a map, or of characters in a string
+pub fn len(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let length = match arguments[0] {
+        Value::Obj(reference) => match heap.get(reference) {
+            Obj::List(elements) => Some(elements.len()),
+            Obj::Map(entries) => Some(entries.len()),
+            Obj::String(string) => Some(string.chars().count()),
+            _ => None,
+        },
+        _ => None,
+    };
+    length.map(|length| Value::Number(length as f64))
+        .ok_or_else(|| "len() expects a list, a map or a string".to_string())
+}
+
+pub fn push(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let list = expect_list(heap, "push", arguments[0])?;
+    heap.list_mut(list).push(arguments[1]);
+    Ok(Value::Nil)
+}
+
+pub fn pop(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let list = expect_list(heap, "pop", arguments[0])?;
+    heap.list_mut(list).pop().ok_or_else(|| "Can't pop from an empty list".to_string())
+}
+
+// Inserts before the element at the index; the list's length appends
+pub fn insert(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let list = expect_list(heap, "insert", arguments[0])?;
+    let position = list::boundary(expect_index("insert", arguments[1])?, heap.list(list).len())?;
+    heap.list_mut(list).insert(position, arguments[2]);
+    Ok(Value::Nil)
+}
+
+// Returns the element that was removed
+pub fn remove(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let list = expect_list(heap, "remove", arguments[0])?;
+    let position = list::element(expect_index("remove", arguments[1])?, heap.list(list).len())?;
+    Ok(heap.list_mut(list).remove(position))
+}
+
+// Keys and values come out in the order the keys were first added
+pub fn keys(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let map = expect_map(heap, "keys", arguments[0])?;
+    let keys = heap.map(map).keys().map(Value::from).collect();
+    Ok(Value::Obj(heap.alloc(Obj::List(keys))))
+}
+
+pub fn values(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let map = expect_map(heap, "values", arguments[0])?;
+    let values = heap.map(map).values().copied().collect();
+    Ok(Value::Obj(heap.alloc(Obj::List(values))))
+}
+
+pub fn has(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let map = expect_map(heap, "has", arguments[0])?;
+    let key = heap.key(arguments[1])?;
+    Ok(Value::Bool(heap.map(map).contains(&key)))
+}
+
+// Returns the value that was removed
+pub fn delete(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    let map = expect_map(heap, "delete", arguments[0])?;
+    let key = heap.key(arguments[1])?;
+    let removed = heap.map_mut(map).remove(&key);
+    removed.ok_or_else(|| format!("Undefined key {}", heap.format(arguments[1])))
+}
+
+pub fn range(heap: &mut Heap, arguments: &[Value]) -> Result<Value, String> {
+    match (arguments[0], arguments[1]) {
+        (Value::Number(start), Value::Number(end)) => {
+            let numbers = list::range(start, end)?.into_iter().map(Value::Number).collect();
+            Ok(Value::Obj(heap.alloc(Obj::List(numbers))))
+        }
+        _ => Err("range() expects numbers".to_string()),
+    }
+}
+
+fn expect_list(heap: &Heap, name: &str, argument: Value) -> Result<ObjRef, String> {
+    match argument {
+        Value::Obj(reference) if matches!(heap.get(reference), Obj::List(_)) => Ok(reference),
+        _ => Err(format!("{}() expects a list", name)),
+    }
+}
+
+fn expect_map(heap: &Heap, name: &str, argument: Value) -> Result<ObjRef, String> {
+    match argument {
+        Value::Obj(reference) if matches!(heap.get(reference), Obj::Map(_)) => Ok(reference),
+        _ => Err(format!("{}() expects a map", name)),
+    }
+}
+
+fn expect_index(name: &str, argument: Value) -> Result<f64, String> {
+    match argument {
+        Value::Number(index) => Ok(index),
+        _ => Err(format!("{}() expects a number index", name)),
+    }
+}
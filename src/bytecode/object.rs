@@ -0,0 +1,72 @@
+use crate::bytecode::chunk::Chunk;
+use crate::bytecode::heap::Heap;
+use crate::bytecode::value::{ObjRef, Value};
+use crate::common::map::{Key, Map};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub enum Obj {
+    String(String),
+    Function(Function),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    Native(Native),
+    List(Vec<Value>),
+    // String keys are interned, so their handles compare by contents
+    Map(Map<Key<ObjRef>, Value>),
+    Cursor(Cursor),
+}
+
+// The chunk is shared with every call frame running the function
+pub struct Function {
+    pub name: Option<ObjRef>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Rc<Chunk>,
+}
+
+// Natives get the heap so they can read and change objects. Allocating from it directly
+// never triggers a collection.
+pub type NativeFn = fn(&mut Heap, &[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+pub struct Closure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+// A captured variable lives on the stack until its scope ends, then moves into the upvalue
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Class {
+    pub name: ObjRef,
+    pub methods: HashMap<ObjRef, ObjRef>,
+}
+
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<ObjRef, Value>,
+}
+
+// How far a for-in loop has got through a list, or through a string. The position of a
+// string cursor is a byte offset. Maps are iterated through a list of their keys.
+pub struct Cursor {
+    pub source: ObjRef,
+    pub position: usize,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
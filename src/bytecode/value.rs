@@ -0,0 +1,35 @@
+use crate::common::map::{self, Key};
+
+// Handle to an object owned by the Heap
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct ObjRef(pub usize);
+
+// Strings are interned, so comparing two string handles compares their contents
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    pub fn is_falsey(&self) -> bool {
+        match self {
+            Value::Nil => true,
+            Value::Bool(value) => !value,
+            _ => false,
+        }
+    }
+}
+
+impl From<&Key<ObjRef>> for Value {
+    fn from(key: &Key<ObjRef>) -> Value {
+        match key {
+            Key::Nil => Value::Nil,
+            Key::Bool(value) => Value::Bool(*value),
+            Key::Number(bits) => Value::Number(map::key_number(*bits)),
+            Key::String(string) => Value::Obj(*string),
+        }
+    }
+}
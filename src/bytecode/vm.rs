@@ -0,0 +1,847 @@
+use crate::bytecode::chunk::{Chunk, OpCode};
+use crate::bytecode::compiler;
+use crate::bytecode::heap::Heap;
+use crate::bytecode::object::{Obj, Closure, Upvalue, Class, Instance, BoundMethod, Native, NativeFn, Cursor};
+use crate::bytecode::value::{ObjRef, Value};
+use crate::common::LoxError;
+use crate::bytecode::debug;
+use crate::bytecode::native;
+use crate::common::list;
+use crate::common::map::Map;
+use crate::common::output::Output;
+use crate::common::trace::Trace;
+use crate::common::token::Span;
+use crate::common::statement::Stmt;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+const FRAMES_MAX: usize = 1024;
+
+// `slots` is the stack index of the called closure, which local slot zero refers to
+struct CallFrame {
+    closure: ObjRef,
+    chunk: Rc<Chunk>,
+    ip: usize,
+    slots: usize,
+}
+
+// Globals outlive a single call to interpret, so a VM can be fed one REPL line at a time
+pub struct VM {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<ObjRef, Value>,
+    // Sorted by the stack slot they point at, so closing a scope only checks the end
+    open_upvalues: Vec<ObjRef>,
+    init_string: ObjRef,
+    // The methods of the iterator protocol used by for-in loops
+    iterator_string: ObjRef,
+    has_next_string: ObjRef,
+    next_string: ObjRef,
+    output: Output,
+    trace: Trace,
+}
+
+impl Default for VM {
+    fn default() -> VM {
+        VM::new()
+    }
+}
+
+impl VM {
+    pub fn new() -> VM {
+        let mut heap = Heap::new();
+        let init_string = heap.intern("init");
+        let iterator_string = heap.intern("iterator");
+        let has_next_string = heap.intern("hasNext");
+        let next_string = heap.intern("next");
+        let mut vm = VM {
+            heap,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            init_string,
+            iterator_string,
+            has_next_string,
+            next_string,
+            output: Output::stdout(),
+            trace: Trace::default(),
+        };
+        vm.define_native("clock", 0, native::clock);
+        vm.define_native("len", 1, native::len);
+        vm.define_native("push", 2, native::push);
+        vm.define_native("pop", 1, native::pop);
+        vm.define_native("insert", 3, native::insert);
+        vm.define_native("remove", 2, native::remove);
+        vm.define_native("keys", 1, native::keys);
+        vm.define_native("values", 1, native::values);
+        vm.define_native("has", 2, native::has);
+        vm.define_native("delete", 2, native::delete);
+        vm.define_native("range", 2, native::range);
+        vm
+    }
+
+    // Crate-internal: natives work on the VM's heap, which isn't part of the public API.
+    // Hosts that want their own functions use the tree-walk interpreter's define_native.
+    pub(crate) fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = Native { name: name.to_string(), arity, function };
+        let name = self.heap.intern(name);
+        let native = self.heap.alloc(Obj::Native(native));
+        self.globals.insert(name, Value::Obj(native));
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt], is_repl: bool) -> Result<(), LoxError> {
+        let function = compiler::compile(statements, &mut self.heap, is_repl)?;
+
+        // The function is kept on the stack so a collection while allocating its closure
+        // can't free it
+        self.stack.push(Value::Obj(function));
+        let closure = self.alloc(Obj::Closure(Closure { function, upvalues: Vec::new() }));
+        self.pop();
+        self.stack.push(Value::Obj(closure));
+
+        let result = self.call(closure, 0).and_then(|_| self.run());
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let name = self.heap.find_string(name)?;
+        self.globals.get(&name).copied()
+    }
+
+    // Global names and their values, sorted by name
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals: Vec<(String, Value)> = self.globals.iter()
+            .map(|(name, value)| (self.heap.string(*name).to_string(), *value))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    // Forgets every global except natives, keeping the collector's settings
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        let heap = &self.heap;
+        self.globals.retain(|_, value| match value {
+            Value::Obj(reference) => matches!(heap.get(*reference), Obj::Native(_)),
+            _ => false,
+        });
+        self.collect_garbage();
+    }
+
+    pub fn format(&self, value: Value) -> String {
+        self.heap.format(value)
+    }
+
+    // Writes the stack and the disassembled instruction before each instruction runs.
+    // Does nothing unless built with the `trace` feature.
+    pub fn set_trace(&mut self, writer: Box<dyn Write>) {
+        self.trace.enable(writer);
+    }
+
+    // Sends the output of print statements to the writer instead of stdout
+    pub fn set_output(&mut self, writer: Box<dyn Write>) {
+        self.output.redirect(writer);
+    }
+
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    pub fn set_gc_growth_factor(&mut self, growth_factor: usize) {
+        self.heap.set_growth_factor(growth_factor);
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.heap.live_objects()
+    }
+
+    // Frees every object unreachable from the stack, globals and open upvalues. Returns
+    // the number of objects freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut roots: Vec<ObjRef> = self.stack.iter()
+            .filter_map(|value| match value {
+                Value::Obj(reference) => Some(*reference),
+                _ => None,
+            })
+            .collect();
+        roots.extend(self.frames.iter().map(|frame| frame.closure));
+        roots.extend(self.open_upvalues.iter().copied());
+        for (name, value) in &self.globals {
+            roots.push(*name);
+            if let Value::Obj(reference) = value {
+                roots.push(*reference);
+            }
+        }
+        roots.extend([self.init_string, self.iterator_string, self.has_next_string, self.next_string]);
+
+        self.heap.collect(&roots)
+    }
+
+    // Every allocation made while running may trigger a collection, so anything the new
+    // object refers to must already be reachable from a root
+    fn alloc(&mut self, object: Obj) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    fn intern(&mut self, string: &str) -> ObjRef {
+        if self.heap.find_string(string).is_none() && self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.intern(string)
+    }
+
+    fn run(&mut self) -> Result<(), LoxError> {
+        loop {
+            self.trace.event(|| self.trace_instruction());
+            let byte = self.read_byte();
+            let op = match OpCode::from_byte(byte) {
+                Some(op) => op,
+                None => return Err(self.error(&format!("Unknown opcode {}", byte))),
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(*value),
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    if !self.globals.contains_key(&name) {
+                        return Err(self.undefined_variable(name));
+                    }
+                    self.globals.insert(name, self.peek(0));
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = match self.heap.upvalue(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot],
+                        Upvalue::Closed(value) => *value,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(0) {
+                        Value::Obj(reference) => match self.heap.get(reference) {
+                            Obj::Instance(instance) => instance,
+                            _ => return Err(self.error("Only instances have properties")),
+                        },
+                        _ => return Err(self.error("Only instances have properties")),
+                    };
+
+                    if let Some(value) = instance.fields.get(&name).copied() {
+                        self.pop();
+                        self.stack.push(value);
+                    } else {
+                        let class = instance.class;
+                        self.bind_method(class, name)?;
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(1) {
+                        Value::Obj(reference) if self.is_instance(reference) => reference,
+                        _ => return Err(self.error("Only instances have fields")),
+                    };
+                    let value = self.pop();
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    match self.pop() {
+                        Value::Obj(superclass) => self.bind_method(superclass, name)?,
+                        _ => return Err(self.error("Can't use 'super' outside of a subclass")),
+                    }
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Bool(left == right));
+                }
+                OpCode::Greater => self.compare(|l, r| l > r, |l, r| l > r)?,
+                OpCode::Less => self.compare(|l, r| l < r, |l, r| l < r)?,
+                OpCode::Add => {
+                    let right = self.peek(0);
+                    let left = self.peek(1);
+                    let result = match (left, right) {
+                        (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+                        (Value::Obj(l), Value::Obj(r)) if self.is_string(l) && self.is_string(r) => {
+                            let concatenated = format!("{}{}", self.heap.string(l), self.heap.string(r));
+                            Value::Obj(self.intern(&concatenated))
+                        }
+                        _ => return Err(self.error(ARITHMETIC_ERROR)),
+                    };
+                    self.pop();
+                    self.pop();
+                    self.stack.push(result);
+                }
+                OpCode::Subtract => self.arithmetic(|l, r| l - r)?,
+                OpCode::Multiply => self.arithmetic(|l, r| l * r)?,
+                OpCode::Divide => self.arithmetic(|l, r| l / r)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Number(number) => {
+                        self.pop();
+                        self.stack.push(Value::Number(-number));
+                    }
+                    _ => return Err(self.error("Cannot perform -/1 on a non-number")),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.heap.format(value);
+                    self.output.print_line(&text).map_err(|message| self.error(&message))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short() as usize;
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argument_count = self.read_byte() as usize;
+                    self.call_value(self.peek(argument_count), argument_count)?;
+                }
+                OpCode::Closure => self.closure()?,
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slots);
+
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Obj::Class(Class { name, methods: HashMap::new() }));
+                    self.stack.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Obj(reference) if self.is_class(reference) => reference,
+                        _ => return Err(self.error("Superclass must be a class")),
+                    };
+                    let subclass = match self.peek(0) {
+                        Value::Obj(reference) => reference,
+                        _ => unreachable!("Expected a class"),
+                    };
+
+                    // Methods are copied down, so later lookups never walk the superclass chain
+                    let methods = self.heap.class(superclass).methods.clone();
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = match self.peek(0) {
+                        Value::Obj(reference) => reference,
+                        _ => unreachable!("Expected a closure"),
+                    };
+                    if let Value::Obj(class) = self.peek(1) {
+                        self.heap.class_mut(class).methods.insert(name, method);
+                    }
+                    self.pop();
+                }
+                OpCode::BuildList => {
+                    // The elements stay on the stack until the list holding them exists
+                    let count = self.read_byte() as usize;
+                    let elements = self.stack[self.stack.len() - count..].to_vec();
+                    let list = self.alloc(Obj::List(elements));
+                    self.stack.truncate(self.stack.len() - count);
+                    self.stack.push(Value::Obj(list));
+                }
+                OpCode::BuildMap => {
+                    // Strings used as keys stay reachable from the stack until the map is built
+                    let count = self.read_byte() as usize;
+                    let mut entries = Map::new();
+                    for pair in self.stack[self.stack.len() - 2 * count..].chunks(2) {
+                        let key = self.heap.key(pair[0]).map_err(|message| self.error(&message))?;
+                        entries.insert(key, pair[1]);
+                    }
+                    let map = self.alloc(Obj::Map(entries));
+                    self.stack.truncate(self.stack.len() - 2 * count);
+                    self.stack.push(Value::Obj(map));
+                }
+                OpCode::GetIterator => self.get_iterator()?,
+                OpCode::HasNext => match self.cursor(self.peek(0)) {
+                    Some(cursor) => {
+                        let Cursor { source, position } = *cursor;
+                        let length = match self.heap.get(source) {
+                            Obj::List(elements) => elements.len(),
+                            _ => self.heap.string(source).len(),
+                        };
+                        self.pop();
+                        self.stack.push(Value::Bool(position < length));
+                    }
+                    None => self.invoke(self.has_next_string)?,
+                },
+                OpCode::Next => match self.cursor(self.peek(0)) {
+                    Some(cursor) => {
+                        let Cursor { source, position } = *cursor;
+                        let (value, length) = match self.heap.get(source) {
+                            Obj::List(elements) => (elements[position], 1),
+                            _ => {
+                                let character = self.heap.string(source)[position..].chars().next().unwrap();
+                                (Value::Obj(self.intern(&character.to_string())), character.len_utf8())
+                            }
+                        };
+                        if let Value::Obj(reference) = self.peek(0) {
+                            if let Obj::Cursor(cursor) = self.heap.get_mut(reference) {
+                                cursor.position += length;
+                            }
+                        }
+                        self.pop();
+                        self.stack.push(value);
+                    }
+                    None => self.invoke(self.next_string)?,
+                },
+                OpCode::GetIndex => {
+                    let value = self.get_index(self.peek(1), self.peek(0))?;
+                    self.pop();
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetIndex => {
+                    self.set_index(self.peek(2), self.peek(1), self.peek(0))?;
+                    let value = self.pop();
+                    self.pop();
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::Slice => {
+                    let list = match self.peek(2) {
+                        Value::Obj(reference) if matches!(self.heap.get(reference), Obj::List(_)) => reference,
+                        _ => return Err(self.error("Only lists can be sliced")),
+                    };
+                    let bounds = (self.slice_bound(self.peek(1))?, self.slice_bound(self.peek(0))?);
+                    let range = list::slice(bounds.0, bounds.1, self.heap.list(list).len())
+                        .map_err(|message| self.error(&message))?;
+                    let elements = self.heap.list(list)[range].to_vec();
+                    let slice = self.alloc(Obj::List(elements));
+                    self.stack.truncate(self.stack.len() - 3);
+                    self.stack.push(Value::Obj(slice));
+                }
+            }
+        }
+    }
+
+    fn trace_instruction(&self) -> String {
+        let stack: Vec<String> = self.stack.iter().map(|value| self.heap.format(*value)).collect();
+        let mut instruction = String::new();
+        let frame = self.frame();
+        debug::disassemble_instruction(&self.heap, &frame.chunk, frame.ip, &mut instruction);
+        format!("[{}] {}", stack.join(", "), instruction.trim_end())
+    }
+
+    fn call_value(&mut self, callee: Value, argument_count: usize) -> Result<(), LoxError> {
+        let reference = match callee {
+            Value::Obj(reference) => reference,
+            _ => return Err(self.error("Can only call functions and classes")),
+        };
+
+        match self.heap.get(reference) {
+            Obj::Closure(_) => self.call(reference, argument_count),
+            Obj::Native(native) => {
+                if argument_count != native.arity {
+                    return Err(self.error("Arguments do not match Parameter arity"));
+                }
+                let function = native.function;
+                let arguments_start = self.stack.len() - argument_count;
+                let result = function(&mut self.heap, &self.stack[arguments_start..])
+                    .map_err(|message| self.error(&message))?;
+                self.stack.truncate(arguments_start - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            Obj::BoundMethod(BoundMethod { receiver, method }) => {
+                let method = *method;
+                let receiver_slot = self.stack.len() - argument_count - 1;
+                self.stack[receiver_slot] = *receiver;
+                self.call(method, argument_count)
+            }
+            Obj::Class(class) => {
+                let initializer = class.methods.get(&self.init_string).copied();
+                let instance = Instance { class: reference, fields: HashMap::new() };
+                let instance = self.alloc(Obj::Instance(instance));
+                let receiver_slot = self.stack.len() - argument_count - 1;
+                self.stack[receiver_slot] = Value::Obj(instance);
+
+                match initializer {
+                    Some(initializer) => self.call(initializer, argument_count),
+                    None if argument_count != 0 => Err(self.error("Arguments do not match Parameter arity")),
+                    None => Ok(()),
+                }
+            }
+            _ => Err(self.error("Can only call functions and classes")),
+        }
+    }
+
+    fn call(&mut self, closure: ObjRef, argument_count: usize) -> Result<(), LoxError> {
+        let function = self.heap.function(self.heap.closure(closure).function);
+        if argument_count != function.arity {
+            return Err(self.error("Arguments do not match Parameter arity"));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("Stack overflow"));
+        }
+
+        let frame = CallFrame {
+            closure,
+            chunk: Rc::clone(&function.chunk),
+            ip: 0,
+            slots: self.stack.len() - argument_count - 1,
+        };
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    // Replaces the iterable on top of the stack with an iterator over it. An instance's
+    // iterator() method is called, and its result takes the instance's place when it returns.
+    fn get_iterator(&mut self) -> Result<(), LoxError> {
+        let iterable = match self.peek(0) {
+            Value::Obj(reference) => reference,
+            _ => return Err(self.error(ITERATE_ERROR)),
+        };
+        let source = match self.heap.get(iterable) {
+            Obj::List(_) | Obj::String(_) => iterable,
+            Obj::Map(entries) => {
+                // The keys are read up front; the list holding them stays on the stack
+                // until the cursor exists
+                let keys = entries.keys().map(Value::from).collect();
+                let keys = self.alloc(Obj::List(keys));
+                self.stack.push(Value::Obj(keys));
+                keys
+            }
+            Obj::Instance(_) => return self.invoke(self.iterator_string),
+            _ => return Err(self.error(ITERATE_ERROR)),
+        };
+
+        let cursor = self.alloc(Obj::Cursor(Cursor { source, position: 0 }));
+        if source != iterable {
+            self.pop();
+        }
+        self.pop();
+        self.stack.push(Value::Obj(cursor));
+        Ok(())
+    }
+
+    fn cursor(&self, value: Value) -> Option<&Cursor> {
+        match value {
+            Value::Obj(reference) => match self.heap.get(reference) {
+                Obj::Cursor(cursor) => Some(cursor),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Calls a method, or a callable field, of the instance on top of the stack with no
+    // arguments. Its result replaces the instance once it returns.
+    fn invoke(&mut self, name: ObjRef) -> Result<(), LoxError> {
+        let instance = match self.peek(0) {
+            Value::Obj(reference) => match self.heap.get(reference) {
+                Obj::Instance(instance) => instance,
+                _ => return Err(self.error("Only instances have properties")),
+            },
+            _ => return Err(self.error("Only instances have properties")),
+        };
+
+        if let Some(field) = instance.fields.get(&name).copied() {
+            let receiver_slot = self.stack.len() - 1;
+            self.stack[receiver_slot] = field;
+            return self.call_value(field, 0);
+        }
+        match self.heap.class(instance.class).methods.get(&name).copied() {
+            Some(method) => self.call(method, 0),
+            None => {
+                let message = format!("Undefined property '{}'", self.heap.string(name));
+                Err(self.error(&message))
+            }
+        }
+    }
+
+    // Replaces the instance on top of the stack with its class's method bound to it
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), LoxError> {
+        let method = match self.heap.class(class).methods.get(&name) {
+            Some(method) => *method,
+            None => {
+                let message = format!("Undefined property '{}'", self.heap.string(name));
+                return Err(self.error(&message));
+            }
+        };
+
+        let receiver = self.peek(0);
+        let bound_method = self.alloc(Obj::BoundMethod(BoundMethod { receiver, method }));
+        self.pop();
+        self.stack.push(Value::Obj(bound_method));
+        Ok(())
+    }
+
+    fn closure(&mut self) -> Result<(), LoxError> {
+        let function = match self.read_constant() {
+            Value::Obj(reference) => reference,
+            _ => unreachable!("Expected a function"),
+        };
+
+        let upvalue_count = self.heap.function(function).upvalue_count;
+        let mut upvalues = Vec::with_capacity(upvalue_count);
+        for _ in 0..upvalue_count {
+            let is_local = self.read_byte() == 1;
+            let index = self.read_byte() as usize;
+            let upvalue = if is_local {
+                self.capture_upvalue(self.frame().slots + index)
+            } else {
+                self.heap.closure(self.frame().closure).upvalues[index]
+            };
+            upvalues.push(upvalue);
+        }
+
+        let closure = self.alloc(Obj::Closure(Closure { function, upvalues }));
+        self.stack.push(Value::Obj(closure));
+        Ok(())
+    }
+
+    // Closures capturing the same variable must share a single upvalue
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let mut insert_at = self.open_upvalues.len();
+        for (index, upvalue) in self.open_upvalues.iter().enumerate().rev() {
+            match self.heap.upvalue(*upvalue) {
+                Upvalue::Open(open_slot) if *open_slot == slot => return *upvalue,
+                Upvalue::Open(open_slot) if *open_slot < slot => break,
+                _ => insert_at = index,
+            }
+        }
+
+        let upvalue = self.alloc(Obj::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.insert(insert_at, upvalue);
+        upvalue
+    }
+
+    // Moves every variable at or above `last` off the stack and into its upvalue
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(upvalue) = self.open_upvalues.last().copied() {
+            let slot = match self.heap.upvalue(upvalue) {
+                Upvalue::Open(slot) if *slot >= last => *slot,
+                _ => break,
+            };
+            *self.heap.upvalue_mut(upvalue) = Upvalue::Closed(self.stack[slot]);
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn arithmetic(&mut self, operation: fn(f64, f64) -> f64) -> Result<(), LoxError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(l), Value::Number(r)) => {
+                self.pop();
+                self.pop();
+                self.stack.push(Value::Number(operation(l, r)));
+                Ok(())
+            }
+            _ => Err(self.error(ARITHMETIC_ERROR)),
+        }
+    }
+
+    fn compare(&mut self, num_fn: fn(f64, f64) -> bool, str_fn: fn(&str, &str) -> bool) -> Result<(), LoxError> {
+        let result = match (self.peek(1), self.peek(0)) {
+            (Value::Number(l), Value::Number(r)) => num_fn(l, r),
+            (Value::Obj(l), Value::Obj(r)) if self.is_string(l) && self.is_string(r) => {
+                str_fn(self.heap.string(l), self.heap.string(r))
+            }
+            _ => return Err(self.error(ARITHMETIC_ERROR)),
+        };
+        self.pop();
+        self.pop();
+        self.stack.push(Value::Bool(result));
+        Ok(())
+    }
+
+    fn get_index(&self, object: Value, index: Value) -> Result<Value, LoxError> {
+        let value = match self.heap.get(self.subscripted(object)?) {
+            Obj::List(elements) => self.element(index, elements.len()).map(|position| elements[position]),
+            Obj::Map(entries) => self.heap.key(index).and_then(|key| {
+                entries.get(&key).copied().ok_or_else(|| format!("Undefined key {}", self.heap.format(index)))
+            }),
+            _ => unreachable!("Expected a list or a map"),
+        };
+        value.map_err(|message| self.error(&message))
+    }
+
+    // Assigning to a key a map doesn't have adds it
+    fn set_index(&mut self, object: Value, index: Value, value: Value) -> Result<(), LoxError> {
+        let reference = self.subscripted(object)?;
+        let stored = match self.heap.get(reference) {
+            Obj::List(elements) => self.element(index, elements.len())
+                .map(|position| self.heap.list_mut(reference)[position] = value),
+            _ => self.heap.key(index).map(|key| self.heap.map_mut(reference).insert(key, value)),
+        };
+        stored.map_err(|message| self.error(&message))
+    }
+
+    fn subscripted(&self, object: Value) -> Result<ObjRef, LoxError> {
+        match object {
+            Value::Obj(reference) if matches!(self.heap.get(reference), Obj::List(_) | Obj::Map(_)) => Ok(reference),
+            _ => Err(self.error(INDEX_ERROR)),
+        }
+    }
+
+    fn element(&self, index: Value, length: usize) -> Result<usize, String> {
+        match index {
+            Value::Number(index) => list::element(index, length),
+            _ => Err("Index must be a number".to_string()),
+        }
+    }
+
+    // A bound left out of a slice is compiled as nil
+    fn slice_bound(&self, bound: Value) -> Result<Option<f64>, LoxError> {
+        match bound {
+            Value::Nil => Ok(None),
+            Value::Number(bound) => Ok(Some(bound)),
+            _ => Err(self.error("Slice bounds must be numbers")),
+        }
+    }
+
+    fn is_string(&self, reference: ObjRef) -> bool {
+        matches!(self.heap.get(reference), Obj::String(_))
+    }
+
+    fn is_class(&self, reference: ObjRef) -> bool {
+        matches!(self.heap.get(reference), Obj::Class(_))
+    }
+
+    fn is_instance(&self, reference: ObjRef) -> bool {
+        matches!(self.heap.get(reference), Obj::Instance(_))
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_short(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let short = frame.chunk.read_short(frame.ip);
+        frame.ip += 2;
+        short
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        self.frame().chunk.constants[index]
+    }
+
+    fn read_string(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Obj(reference) => reference,
+            _ => unreachable!("Expected a string constant"),
+        }
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Stack underflow")
+    }
+
+    fn undefined_variable(&self, name: ObjRef) -> LoxError {
+        let message = format!("Undefined variable '{}'", self.heap.string(name));
+        self.error(&message)
+    }
+
+    // Reports the position of the instruction currently executing
+    fn error(&self, message: &str) -> LoxError {
+        let span = match self.frames.last() {
+            Some(frame) => frame.chunk.spans[frame.ip.saturating_sub(1)],
+            None => Span::host(),
+        };
+        LoxError::Runtime(span, message.to_string())
+    }
+}
+
+const ITERATE_ERROR: &str = "Can only iterate over lists, maps, strings and iterators";
+
+const INDEX_ERROR: &str = "Only lists and maps can be indexed";
+
+const ARITHMETIC_ERROR: &str = "Couldn't perform binary arithmetic because types didn't match/weren't supported";
@@ -0,0 +1,80 @@
+use crate::common::token::{Token, Span};
+use crate::common::token::token_type::TokenType;
+use std::fmt;
+
+// Every error carries the span it was found at. A column of 0 means only the line is known,
+// and a line of 0 that the error didn't come from the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxError {
+    Scan(Span, String),
+    Parse(Span, String),
+    Resolve(Span, String),
+    Runtime(Span, String),
+    Compile(Span, String),
+}
+
+impl LoxError {
+    pub fn parse(token: &Token, message: &str) -> LoxError {
+        LoxError::Parse(token.span(), format!("{}: {}", location(token), message))
+    }
+
+    pub fn resolve(token: &Token, message: &str) -> LoxError {
+        LoxError::Resolve(token.span(), format!("{}: {}", location(token), message))
+    }
+
+    pub fn runtime(token: &Token, message: &str) -> LoxError {
+        LoxError::Runtime(token.span(), message.to_string())
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            LoxError::Scan(span, _) => *span,
+            LoxError::Parse(span, _) => *span,
+            LoxError::Resolve(span, _) => *span,
+            LoxError::Runtime(span, _) => *span,
+            LoxError::Compile(span, _) => *span,
+        }
+    }
+
+    pub fn line(&self) -> u32 {
+        self.span().line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.span().column
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            LoxError::Scan(_, message) => message,
+            LoxError::Parse(_, message) => message,
+            LoxError::Resolve(_, message) => message,
+            LoxError::Runtime(_, message) => message,
+            LoxError::Compile(_, message) => message,
+        }
+    }
+}
+
+fn location(token: &Token) -> String {
+    match token.type_of {
+        TokenType::Eof => "at end".to_string(),
+        _ => format!("at '{}'", token.text()),
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self {
+            LoxError::Scan(_, _) => "Scan",
+            LoxError::Parse(_, _) => "Parse",
+            LoxError::Resolve(_, _) => "Resolve",
+            LoxError::Runtime(_, _) => "Runtime",
+            LoxError::Compile(_, _) => "Compile",
+        };
+        match (self.line(), self.column()) {
+            (0, _) => write!(f, "{} Error: {}", stage, self.message()),
+            (line, 0) => write!(f, "Line: {} {} Error: {}", line, stage, self.message()),
+            (line, column) => write!(f, "Line: {}:{} {} Error: {}", line, column, stage, self.message()),
+        }
+    }
+}
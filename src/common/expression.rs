@@ -0,0 +1,70 @@
+use crate::common::token::{Token, Literal};
+use crate::common::statement::Stmt;
+
+#[derive(Clone, PartialOrd, PartialEq)]
+// Variable and Assign carry the scope depth filled in by the resolver; None means global.
+// This and Super always resolve to the method that binds them.
+pub enum Expr {
+    Assign(Token, Box<Expr>, Option<usize>),
+    Binary(Box<Expr>, Token, Box<Expr>),
+    Call(Box<Expr>, Token, Vec<Expr>),
+    Get(Box<Expr>, Token),
+    Set(Box<Expr>, Token, Box<Expr>),
+    // Subscripts carry their '['. A slice bound that was left out is Empty.
+    List(Token, Vec<Expr>),
+    // Key and value pairs, in the order they were written
+    Map(Token, Vec<(Expr, Expr)>),
+    Index(Box<Expr>, Token, Box<Expr>),
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    This(Token, usize),
+    Super(Token, Token, usize),
+    Literal(Literal),
+    Logical(Box<Expr>, Token, Box<Expr>),
+    Unary(Token, Box<Expr>),
+    Variable(Token, Option<usize>),
+    Grouping(Box<Expr>),
+    // An anonymous function; the token is `fun`, or the `(` opening an arrow function
+    Lambda(Token, Vec<Token>, Vec<Stmt>),
+    Empty    // TODO: This is temporary!!!
+}
+
+impl std::fmt::Debug for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Expr::Assign(token, expr, _) => {
+                write!(f, "(Assign {:?}, {:?})", token, expr)
+            },
+            Expr::Binary(left, token, right) => {
+                write!(f, "({:?} {:?} {:?})", token, left, right)
+            },
+            Expr::Literal(literal) => write!(f, "{:?}", literal),
+            Expr::Logical(left, operator, right) => {
+                write!(f, "({:?} {:?}, {:?})", operator, left, right)
+            }
+            Expr::Unary(token, expr) => write!(f, "({:?} {:?})", token, expr),
+            Expr::Variable(token, _) => write!(f, "{:?}", token),
+            Expr::Grouping(expr) => write!(f, "({:?})", expr),
+            Expr::Lambda(_, parameters, body) => write!(f, "(Lambda {:?} {:?})", parameters, body),
+            Expr::Empty => write!(f, "()"),
+            Expr::Call(callee, _, arguments) => {
+                write!(f, "({:?} {:?})", callee, arguments)
+            }
+            Expr::Get(object, name) => write!(f, "(Get {:?}.{:?})", object, name),
+            Expr::Set(object, name, value) => {
+                write!(f, "(Set {:?}.{:?}, {:?})", object, name, value)
+            }
+            Expr::List(_, elements) => write!(f, "(List {:?})", elements),
+            Expr::Map(_, entries) => write!(f, "(Map {:?})", entries),
+            Expr::Index(object, _, index) => write!(f, "(Index {:?}[{:?}])", object, index),
+            Expr::SetIndex(object, _, index, value) => {
+                write!(f, "(SetIndex {:?}[{:?}], {:?})", object, index, value)
+            }
+            Expr::Slice(object, _, start, end) => {
+                write!(f, "(Slice {:?}[{:?}:{:?}])", object, start, end)
+            }
+            Expr::This(_, _) => write!(f, "this"),
+            Expr::Super(_, method, _) => write!(f, "(Super {:?})", method),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::ops::Range;
+
+// Index arithmetic shared by both backends. Negative indices count back from the end of
+// the list, so -1 is the last element.
+
+// The position of an existing element
+pub(crate) fn element(index: f64, length: usize) -> Result<usize, String> {
+    position(index, length, false)
+}
+
+// A position between elements, where `length` is just past the last one. Used for insertion
+// points and slice bounds.
+pub(crate) fn boundary(index: f64, length: usize) -> Result<usize, String> {
+    position(index, length, true)
+}
+
+// A missing start or end means the start or end of the list. An end before the start gives
+// an empty slice.
+pub(crate) fn slice(start: Option<f64>, end: Option<f64>, length: usize) -> Result<Range<usize>, String> {
+    let start = match start {
+        Some(start) => boundary(start, length)?,
+        None => 0,
+    };
+    let end = match end {
+        Some(end) => boundary(end, length)?,
+        None => length,
+    };
+    Ok(start..end.max(start))
+}
+
+// The numbers from start up to, but not including, end, counting by one
+pub(crate) fn range(start: f64, end: f64) -> Result<Vec<f64>, String> {
+    if !start.is_finite() || !end.is_finite() {
+        return Err("range() expects finite bounds".to_string());
+    }
+    let count = (end - start).ceil().max(0.0) as usize;
+    Ok((0..count).map(|step| start + step as f64).collect())
+}
+
+fn position(index: f64, length: usize, past_end: bool) -> Result<usize, String> {
+    if index.fract() != 0.0 {
+        return Err(format!("Index {} is not a whole number", index));
+    }
+
+    let position = if index < 0.0 { index + length as f64 } else { index };
+    let limit = if past_end { length as f64 } else { length as f64 - 1.0 };
+    if position < 0.0 || position > limit {
+        return Err(format!("Index {} out of bounds for list of length {}", index, length));
+    }
+    Ok(position as usize)
+}
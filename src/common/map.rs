@@ -0,0 +1,111 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Map storage shared by both backends. Keys are nil, booleans, numbers and strings, each
+// backend choosing how it holds a string. Entries keep the order their keys were first
+// inserted in, so printing and iterating a map gives the same result every run.
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Hash)]
+pub enum Key<S> {
+    Nil,
+    Bool(bool),
+    // The number's bits, which makes keys hashable. Numbers go through Key::number.
+    Number(u64),
+    String(S),
+}
+
+impl<S> Key<S> {
+    // -0 is folded into 0, since the two are equal in Lox. NaN isn't equal to anything,
+    // itself included, so it could never be looked up again.
+    pub fn number(number: f64) -> Result<Key<S>, String> {
+        if number.is_nan() {
+            return Err("NaN can't be used as a map key".to_string());
+        }
+        let number = if number == 0.0 { 0.0 } else { number };
+        Ok(Key::Number(number.to_bits()))
+    }
+}
+
+pub fn key_number(bits: u64) -> f64 {
+    f64::from_bits(bits)
+}
+
+pub const KEY_ERROR: &str = "Map keys must be nil, booleans, numbers or strings";
+
+#[derive(Clone)]
+pub struct Map<K, V> {
+    entries: Vec<(K, V)>,
+    positions: HashMap<K, usize>,
+}
+
+impl<K, V> Default for Map<K, V> {
+    fn default() -> Map<K, V> {
+        Map { entries: Vec::new(), positions: HashMap::new() }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> Map<K, V> {
+    pub fn new() -> Map<K, V> {
+        Map::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.positions.get(key).map(|position| &self.entries[*position].1)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    // A key that is already present keeps its place
+    pub fn insert(&mut self, key: K, value: V) {
+        match self.positions.get(&key) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for (key, _) in &self.entries[position..] {
+            if let Some(later) = self.positions.get_mut(key) {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(K, V)> {
+        self.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+// Maps compare by their entries, in order
+impl<K: PartialEq, V: PartialEq> PartialEq for Map<K, V> {
+    fn eq(&self, other: &Map<K, V>) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl<K: PartialOrd, V: PartialOrd> PartialOrd for Map<K, V> {
+    fn partial_cmp(&self, other: &Map<K, V>) -> Option<Ordering> {
+        self.entries.partial_cmp(&other.entries)
+    }
+}
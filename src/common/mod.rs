@@ -0,0 +1,366 @@
+pub(crate) mod token;
+pub(crate) mod scanner;
+pub(crate) mod expression;
+pub(crate) mod statement;
+pub(crate) mod parser;
+pub(crate) mod resolver;
+pub(crate) mod list;
+pub(crate) mod map;
+pub(crate) mod output;
+pub(crate) mod trace;
+mod error;
+
+pub use self::error::LoxError;
+pub use self::trace::TRACE_AVAILABLE;
+
+
+#[cfg(test)]
+mod tests {
+    use crate::common::scanner::Scanner;
+    use crate::common::token::token_type::TokenType;
+    use crate::common::token::{Token, Literal, Span};
+    use crate::common::parser::*;
+    use crate::common::resolver::*;
+    use crate::common::statement::Stmt;
+    use crate::common::expression::Expr;
+    use crate::common::LoxError;
+
+    fn span(line: u32, column: u32, start: usize, end: usize) -> Span {
+        Span { line, column, start, end }
+    }
+
+    #[test]
+    fn test_eof() {
+        let input = "";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new(TokenType::Eof, "", 1).at(span(1, 1, 0, 0)));
+    }
+
+    #[test]
+    fn test_single_char() {
+        let input = "=";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new(TokenType::Equal, "", 1).at(span(1, 1, 0, 1)));
+    }
+
+    #[test]
+    fn test_double_char() {
+        let input = "==";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new(TokenType::EqualEqual, "", 1).at(span(1, 1, 0, 2)));
+    }
+
+    #[test]
+    fn test_multi_char() {
+        let input = "= !=";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new(TokenType::Equal, "", 1).at(span(1, 1, 0, 1)));
+        assert_eq!(tok[1], Token::new(TokenType::BangEqual, "", 1).at(span(1, 3, 2, 4)));
+    }
+
+    #[test]
+    fn test_number() {
+        let input = "1";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new_number(1.0, 1).at(span(1, 1, 0, 1)));
+    }
+
+    #[test]
+    fn test_number_decimal() {
+        let input = "1.23";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new_number(1.23, 1).at(span(1, 1, 0, 4)));
+    }
+
+    #[test]
+    fn test_string() {
+        let input = "\"heya\"";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new_string("heya", 1).at(span(1, 1, 0, 6)));
+    }
+
+    #[test]
+    fn test_keyword() {
+        let input = "and or while";
+        let scanner = Scanner::new(input);
+        let tok = scanner.scan_tokens().unwrap();
+        assert_eq!(tok[0], Token::new_keyword(TokenType::And, 1).at(span(1, 1, 0, 3)));
+        assert_eq!(tok[1], Token::new_keyword(TokenType::Or, 1).at(span(1, 5, 4, 6)));
+        assert_eq!(tok[2], Token::new_keyword(TokenType::While, 1).at(span(1, 8, 7, 12)));
+    }
+
+    #[test]
+    fn test_token_positions() {
+        let input = "var a = 1;\n  print \"\u{e9}\" + a;";
+        let tok = Scanner::new(input).scan_tokens().unwrap();
+        assert_eq!(tok[5].span(), span(2, 3, 13, 18));
+        assert_eq!(tok[6].span(), span(2, 9, 19, 23));
+        assert_eq!(tok[7].column(), 13);
+        assert_eq!(&input[tok[8].span().start..tok[8].span().end], "a");
+        assert_eq!(tok[10].span(), span(2, 17, input.len(), input.len()));
+    }
+
+    #[test]
+    fn test_statement_keeps_keyword_position() {
+        let mut tokens = Scanner::new("fun f() {\n    return 1;\n}").scan_tokens().unwrap();
+        let statements = parse(&mut tokens).unwrap();
+        match &statements[0] {
+            Stmt::Function(_, _, body) => match &body[0] {
+                Stmt::Return(keyword, _) => assert_eq!(keyword.span(), span(2, 5, 14, 20)),
+                statement => panic!("Expected return, got {:?}", statement),
+            },
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_needs_more_input() {
+        assert!(Scanner::new("fun f() {").needs_more_input());
+        assert!(Scanner::new("print (1 +").needs_more_input());
+        assert!(Scanner::new("print \"abc").needs_more_input());
+        assert!(Scanner::new("/* comment").needs_more_input());
+        assert!(!Scanner::new("fun f() { print 1; }").needs_more_input());
+        assert!(!Scanner::new("print 1; }").needs_more_input());
+        assert!(!Scanner::new("print @;").needs_more_input());
+    }
+
+    #[test]
+    fn test_parse_class() {
+        let input = "class Foo { init(x) { this.x = x; } bar() { return this.x; } }";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let statements = parse(&mut tokens).unwrap();
+        match &statements[0] {
+            Stmt::Class(name, superclass, methods) => {
+                assert_eq!(name.lexeme, "Foo");
+                assert_eq!(*superclass, None);
+                assert_eq!(methods.len(), 2);
+            }
+            statement => panic!("Expected class, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_init_cant_return_a_value() {
+        let mut tokens = Scanner::new("class Foo { init() { return 1; } }").scan_tokens().unwrap();
+        let mut statements = parse(&mut tokens).unwrap();
+        let errors = resolve(&mut statements).err().unwrap();
+        assert_eq!(errors, vec![LoxError::Resolve(span(1, 22, 21, 27), "at 'return': Can't return a value from an initializer".to_string())]);
+
+        let mut tokens = Scanner::new("class Foo { init() { fun f() { return 1; } return; } }").scan_tokens().unwrap();
+        let mut statements = parse(&mut tokens).unwrap();
+        assert!(resolve(&mut statements).is_ok());
+    }
+
+    #[test]
+    fn test_parse_subclass() {
+        let input = "class Bar < Foo {}";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let statements = parse(&mut tokens).unwrap();
+        match &statements[0] {
+            Stmt::Class(_, Some(superclass), _) => {
+                assert_eq!(**superclass, Expr::Variable(Token::new_identifier("Foo", 1).at(span(1, 13, 12, 15)), None));
+            }
+            statement => panic!("Expected subclass, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_misused_this_and_super_are_resolve_errors() {
+        let error = |input: &str| -> LoxError {
+            let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+            let mut statements = parse(&mut tokens).unwrap();
+            resolve(&mut statements).err().unwrap().remove(0)
+        };
+        assert_eq!(error("class A { foo() { return super.foo(); } }"),
+                   LoxError::Resolve(span(1, 26, 25, 30), "at 'super': Can't use 'super' in a class with no superclass".to_string()));
+        assert_eq!(error("print super.foo;"),
+                   LoxError::Resolve(span(1, 7, 6, 11), "at 'super': Can't use 'super' outside of a class".to_string()));
+        assert_eq!(error("fun f() { return this; }"),
+                   LoxError::Resolve(span(1, 18, 17, 21), "at 'this': Can't use 'this' outside of a class".to_string()));
+    }
+
+    #[test]
+    fn test_scan_error_line() {
+        let input = "var a = 1;\nvar b = @;";
+        let error = Scanner::new(input).scan_tokens().err().unwrap();
+        assert_eq!(error, LoxError::Scan(span(2, 9, 19, 20), "Unexpected character '@'".to_string()));
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let input = "\"heya";
+        let error = Scanner::new(input).scan_tokens().err().unwrap();
+        assert_eq!(error, LoxError::Scan(span(1, 1, 0, 5), "Unterminated string".to_string()));
+    }
+
+    #[test]
+    fn test_parse_error_line() {
+        let input = "var a = 1;\n\nprint a";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = parse(&mut tokens).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line(), 3);
+    }
+
+    #[test]
+    fn test_parse_error_names_token() {
+        let messages = |input: &str| -> Vec<String> {
+            let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+            parse(&mut tokens).err().unwrap().iter().map(|error| error.message().to_string()).collect()
+        };
+        assert_eq!(messages("print 1"), vec!["at end: Couldn't find ';' at end of statement"]);
+        assert_eq!(messages("var x = ;"), vec!["at ';': Expect expression"]);
+        assert_eq!(messages("var 12 = 1;"), vec!["at '12': Expected identifier"]);
+        assert_eq!(messages("print a b;"), vec!["at 'b': Couldn't find ';' at end of statement"]);
+    }
+
+    #[test]
+    fn test_unclosed_grouping() {
+        let mut tokens = Scanner::new("print (1 2 3);\nprint (4;\nprint (5);").scan_tokens().unwrap();
+        let errors = parse(&mut tokens).err().unwrap();
+        let messages: Vec<(u32, &str)> = errors.iter().map(|error| (error.line(), error.message())).collect();
+        assert_eq!(messages, vec![(1, "at '2': Expect ')' after expression"), (2, "at ';': Expect ')' after expression")]);
+    }
+
+    #[test]
+    fn test_argument_limit() {
+        let arguments = vec!["1"; 255].join(", ");
+        let mut tokens = Scanner::new(&format!("f({});", arguments)).scan_tokens().unwrap();
+        assert!(parse(&mut tokens).is_ok());
+        let mut tokens = Scanner::new(&format!("f({}, 1);", arguments)).scan_tokens().unwrap();
+        assert!(parse(&mut tokens).err().unwrap()[0].message().ends_with("Can't have more than 255 arguments"));
+    }
+
+    #[test]
+    fn test_parse_reports_every_error() {
+        let input = "var = 1;\nprint 2;\nprint ;\nvar b = 3;\nclass {}";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = parse(&mut tokens).err().unwrap();
+        let lines: Vec<u32> = errors.iter().map(|error| error.line()).collect();
+        assert_eq!(lines, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_parse_recovers_inside_block() {
+        let input = "fun foo() {\n  var a = ;\n  print a;\n  a + ;\n}\nprint foo;";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = parse(&mut tokens).err().unwrap();
+        let lines: Vec<u32> = errors.iter().map(|error| error.line()).collect();
+        assert_eq!(lines, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_parse_recovers_at_loop_control_and_block_end() {
+        let lines = |input: &str| -> Vec<u32> {
+            let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+            parse(&mut tokens).err().unwrap().iter().map(|error| error.line()).collect()
+        };
+        assert_eq!(lines("while (true) {\n  print 1 2\n  break 3;\n}"), vec![2, 3]);
+        assert_eq!(lines("while (true) {\n  print 1 2\n  continue 3;\n}"), vec![2, 3]);
+        assert_eq!(lines("{\n  print 1 2 }\nprint ;"), vec![2, 3]);
+        assert_eq!(lines("}\nprint ;"), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_parse_unclosed_block() {
+        let input = "{ print 1;";
+        let mut tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = parse(&mut tokens).err().unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    fn resolve_source(source: &str) -> Result<Vec<Stmt>, Vec<LoxError>> {
+        let mut tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut statements = parse(&mut tokens).unwrap();
+        resolve(&mut statements)?;
+        Ok(statements)
+    }
+
+    #[test]
+    fn test_resolve_depth() {
+        let statements = resolve_source("var g = 1; { var a = 1; { print a; print g; } }").unwrap();
+        let inner = match &statements[1] {
+            Stmt::Block(outer) => outer[1].clone(),
+            statement => panic!("Expected block, got {:?}", statement),
+        };
+        let expected = Stmt::Block(vec![
+            Stmt::Print(Token::new_keyword(TokenType::Print, 1).at(span(1, 27, 26, 31)),
+                        Box::new(Expr::Variable(Token::new_identifier("a", 1).at(span(1, 33, 32, 33)), Some(1)))),
+            Stmt::Print(Token::new_keyword(TokenType::Print, 1).at(span(1, 36, 35, 40)),
+                        Box::new(Expr::Variable(Token::new_identifier("g", 1).at(span(1, 42, 41, 42)), None))),
+        ]);
+        assert_eq!(inner, expected);
+    }
+
+    #[test]
+    fn test_resolve_parameters_share_body_scope() {
+        let statements = resolve_source("fun f(a) { a = 2; return a; }").unwrap();
+        match &statements[0] {
+            Stmt::Function(_, _, body) => {
+                let expected = Stmt::Expr(Box::new(Expr::Assign(
+                    Token::new_identifier("a", 1).at(span(1, 12, 11, 12)),
+                    Box::new(Expr::Literal(Literal::Number(2.0))),
+                    Some(0),
+                )));
+                assert_eq!(body[0], expected);
+            }
+            statement => panic!("Expected function, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_resolve_own_initializer() {
+        let errors = resolve_source("{ var a = 1; { var a = a; } }").err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message().ends_with("Can't read local variable in its own initializer"));
+    }
+
+    #[test]
+    fn test_resolve_duplicate_local() {
+        let errors = resolve_source("fun f(a) { var a = 1; } { var b; var b; }").err().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].message().ends_with("Already a variable with this name in this scope"));
+    }
+
+    #[test]
+    fn test_resolve_top_level_return() {
+        let errors = resolve_source("return 1;").err().unwrap();
+        assert!(errors[0].message().ends_with("Can't return from top-level code"));
+        assert!(resolve_source("fun f() { return 1; }").is_ok());
+    }
+
+    #[test]
+    fn test_resolve_loop_control_outside_loop() {
+        let errors = resolve_source("break;\nfun f() { while (true) { fun g() { continue; } } }").err().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line(), 1);
+        assert!(errors[0].message().ends_with("Can't use 'break' outside of a loop"));
+        assert_eq!(errors[1].line(), 2);
+        assert!(errors[1].message().ends_with("Can't use 'continue' outside of a loop"));
+        assert!(resolve_source("while (true) { if (true) { break; } else continue; }").is_ok());
+    }
+
+    #[test]
+    fn test_resolve_globals_may_be_redeclared() {
+        assert!(resolve_source("var a = 1; var a = a + 1;").is_ok());
+    }
+
+    #[test]
+    fn test_lambda_errors() {
+        let mut tokens = Scanner::new("var f = fun (a) { var; };").scan_tokens().unwrap();
+        assert_eq!(parse(&mut tokens).err().unwrap().len(), 1);
+        // The body recovers, so every error in it is reported along with those after it
+        let mut tokens = Scanner::new("var f = fun (a) {\n var;\n print 1 2;\n};\nprint ;").scan_tokens().unwrap();
+        let lines: Vec<u32> = parse(&mut tokens).err().unwrap().iter().map(LoxError::line).collect();
+        assert_eq!(lines, vec![2, 3, 5]);
+        let mut tokens = Scanner::new("var f = (a) => ;").scan_tokens().unwrap();
+        assert!(parse(&mut tokens).err().unwrap()[0].message().ends_with("Expect expression"));
+    }
+}
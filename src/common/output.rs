@@ -0,0 +1,52 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+// Where `print` statements and REPL echoes go. Clones share one writer, so replacing it
+// also redirects closures and scopes created earlier.
+#[derive(Clone)]
+pub struct Output {
+    writer: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl Output {
+    pub fn stdout() -> Output {
+        let writer: Box<dyn Write> = Box::new(io::stdout());
+        Output { writer: Rc::new(RefCell::new(writer)) }
+    }
+
+    pub fn redirect(&self, writer: Box<dyn Write>) {
+        *self.writer.borrow_mut() = writer;
+    }
+
+    pub fn print_line(&self, text: &str) -> Result<(), String> {
+        let mut writer = self.writer.borrow_mut();
+        writeln!(writer, "{}", text)
+            .and_then(|_| writer.flush())
+            .map_err(|error| format!("Couldn't write output: {}", error))
+    }
+}
+
+// A writer tests can read back after handing it to an interpreter
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,622 @@
+use crate::common::token::{Token, Literal};
+use crate::common::expression::Expr;
+use crate::common::token::token_type::TokenType;
+use crate::common::statement::Stmt;
+use crate::common::error::LoxError;
+
+const EQUALITY_OPS: &[TokenType] = &[TokenType::BangEqual, TokenType::EqualEqual];
+const COMPARISON_OPS: &[TokenType] = &[TokenType::Less, TokenType::LessEqual, TokenType::Greater, TokenType::GreaterEqual];
+const ADDITION_OPS: &[TokenType] = &[TokenType::Plus, TokenType::Minus];
+const MULTIPLICATION_OPS: &[TokenType] = &[TokenType::Star, TokenType::Slash];
+// Shared with the bytecode compiler, which stores counts in a single byte
+pub(crate) const MAX_ARGUMENTS: usize = 255;
+
+const UNARY_OPS: &[TokenType] = &[TokenType::Bang, TokenType::Minus];
+
+// The scanner ends every token stream with Eof, which stays on the stack once reached
+fn peek_token(tokens: &mut [Token]) -> Token {
+    tokens.last()
+        .expect("Tokens should end with Eof")
+        .clone()
+}
+
+// Looks further ahead than peek_token, which sees the token at distance 0
+fn peek_type_at(tokens: &[Token], distance: usize) -> TokenType {
+    tokens.len()
+        .checked_sub(distance + 1)
+        .map_or(TokenType::Eof, |index| tokens[index].type_of)
+}
+
+fn pop_token(tokens: &mut Vec<Token>) -> Token {
+    let token = peek_token(tokens);
+    if token.type_of != TokenType::Eof {
+        tokens.pop();
+    }
+    token
+}
+
+// If the top token is in the family, pop from stack and return true; else false
+fn consume_match(tokens: &mut Vec<Token>, family: &[TokenType]) -> bool {
+    match peek_token(tokens).type_of {
+        TokenType::Eof => false,
+        type_of if family.contains(&type_of) => {
+            pop_token(tokens);
+            true
+        }
+        _ => false,
+    }
+}
+
+// Keeps popping values from the stack until it is empty or the token is found
+// Returns whether or not the token was found before EoF
+fn consume_until_found(tokens: &mut Vec<Token>, family: &[TokenType]) -> bool {
+    match pop_token(tokens).type_of {
+        TokenType::Eof => false,
+        type_of if family.contains(&type_of) => true,
+        _ => consume_until_found(tokens, family),
+    }
+}
+
+fn try_consume(tokens: &mut Vec<Token>, family: &[TokenType], message: &str) -> Result<Token, LoxError> {
+    let token = peek_token(tokens);
+    if !family.contains(&token.type_of) {
+        return Err(LoxError::parse(&token, message));
+    }
+    Ok(pop_token(tokens))
+}
+
+// Parses every declaration in the token stream. Rather than stopping at the first syntax
+// error, the parser recovers at the next statement boundary so that all errors are reported.
+pub fn parse(tokens: &mut Vec<Token>) -> Result<Vec<Stmt>, Vec<LoxError>> {
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    tokens.reverse();   // Treat like a stack
+    while peek_token(tokens).type_of != TokenType::Eof {
+        if let Some(statement) = declaration(tokens, &mut errors) {
+            statements.push(statement);
+        } else if peek_token(tokens).type_of == TokenType::RightBrace {
+            // Recovery stops at a '}', which here has no block to close
+            pop_token(tokens);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+// Returns None if the declaration had a syntax error, which is recorded in errors
+fn declaration(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Option<Stmt> {
+    let result = if consume_match(tokens, &[TokenType::Class]) {
+        class_declaration(tokens, errors)
+    } else if peek_token(tokens).type_of == TokenType::Fun && peek_type_at(tokens, 1) != TokenType::LeftParen {
+        pop_token(tokens);
+        function(tokens, errors, "function")
+    } else if consume_match(tokens, &[TokenType::Var]) {
+        var_declaration(tokens, errors)
+    } else {
+        statement(tokens, errors)
+    };
+
+    match result {
+        Ok(statement) => Some(statement),
+        Err(error) => {
+            errors.push(error);
+            synchronize(tokens);
+            None
+        }
+    }
+}
+
+// Discards tokens until the start of the next statement. The '}' ending a block is left
+// for the block to close.
+fn synchronize(tokens: &mut Vec<Token>) {
+    loop {
+        match peek_token(tokens).type_of {
+            TokenType::RightBrace | TokenType::Eof => return,
+            TokenType::Semicolon => {
+                pop_token(tokens);
+                return;
+            }
+            _ => {
+                pop_token(tokens);
+            }
+        }
+
+        match peek_token(tokens).type_of {
+            TokenType::Class | TokenType::Fun | TokenType::Var | TokenType::For |
+            TokenType::If | TokenType::While | TokenType::Print | TokenType::Return |
+            TokenType::Break | TokenType::Continue => return,
+            _ => {}
+        }
+    }
+}
+
+fn class_declaration(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    let name = try_consume(tokens, &[TokenType::Identifier], "Expected class name")?;
+
+    let superclass = if consume_match(tokens, &[TokenType::Less]) {
+        let superclass = try_consume(tokens, &[TokenType::Identifier], "Expected superclass name")?;
+        Some(Box::new(Expr::Variable(superclass, None)))
+    } else {
+        None
+    };
+
+    try_consume(tokens, &[TokenType::LeftBrace], "Expected '{' before class body")?;
+
+    let mut methods = Vec::new();
+    while peek_token(tokens).type_of != TokenType::RightBrace
+        && peek_token(tokens).type_of != TokenType::Eof {
+        methods.push(function(tokens, errors, "method")?);
+    }
+
+    try_consume(tokens, &[TokenType::RightBrace], "Expected '}' after class body")?;
+    Ok(Stmt::Class(name, superclass, methods))
+}
+
+fn function(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, _kind: &'static str) -> Result<Stmt, LoxError> {
+    let name = try_consume(tokens, &[TokenType::Identifier], "Expected Identifier")?;
+    try_consume(tokens, &[TokenType::LeftParen], "Expected LeftParen")?;
+    let parameters = parameters(tokens)?;
+    try_consume(tokens, &[TokenType::LeftBrace], "Expected LeftBrace")?;
+
+    let body = block(tokens, errors)?;
+
+    Ok(Stmt::Function(name, parameters, body))
+}
+
+// Parses the parameter names following a '(', up to and including the ')'
+fn parameters(tokens: &mut Vec<Token>) -> Result<Vec<Token>, LoxError> {
+    let mut parameters = Vec::new();
+
+    if peek_token(tokens).type_of != TokenType::RightParen {
+        loop {
+            let parameter = peek_token(tokens);
+            if !consume_match(tokens, &[TokenType::Identifier]) {
+                break;
+            }
+            parameters.push(parameter);
+            if parameters.len() > MAX_ARGUMENTS {
+                return Err(LoxError::parse(&parameters[MAX_ARGUMENTS], "Can't have more than 255 parameters"));
+            }
+            if !consume_match(tokens, &[TokenType::Comma]) {
+                break;
+            }
+        }
+    }
+    try_consume(tokens, &[TokenType::RightParen], "Expected RightParen")?;
+    Ok(parameters)
+}
+
+// `fun (a, b) { ... }` has a block body, which recovers from its own errors like the body
+// of a named function
+fn lambda(keyword: Token, tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    try_consume(tokens, &[TokenType::LeftParen], "Expect '(' after 'fun'")?;
+    let parameters = parameters(tokens)?;
+    try_consume(tokens, &[TokenType::LeftBrace], "Expected LeftBrace")?;
+
+    let body = block(tokens, errors)?;
+    Ok(Expr::Lambda(keyword, parameters, body))
+}
+
+// `(a, b) => a + b` returns the value of its expression body
+fn arrow_function(paren: Token, tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let parameters = parameters(tokens)?;
+    let arrow = try_consume(tokens, &[TokenType::Arrow], "Expect '=>' after parameters")?;
+    let body = expression(tokens, errors)?;
+    Ok(Expr::Lambda(paren, parameters, vec![Stmt::Return(arrow, Box::new(body))]))
+}
+
+// Called just after a '('. Arrow parameters are only identifiers and commas, so this looks
+// no further than the first token that couldn't be one.
+fn is_arrow_function(tokens: &[Token]) -> bool {
+    let mut distance = 0;
+    loop {
+        match peek_type_at(tokens, distance) {
+            TokenType::Identifier | TokenType::Comma => distance += 1,
+            TokenType::RightParen => return peek_type_at(tokens, distance + 1) == TokenType::Arrow,
+            _ => return false,
+        }
+    }
+}
+
+fn var_declaration(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    let name = try_consume(tokens, &[TokenType::Identifier], "Expected identifier")?;
+
+    let initializer = if consume_match(tokens, &[TokenType::Equal]) {
+        expression(tokens, errors)?
+    } else {
+        Expr::Empty
+    };
+
+    try_consume(tokens, &[TokenType::Semicolon], "Couldn't find ';' at end of statement")?;
+    Ok(Stmt::Var(name, Box::new(initializer)))
+}
+
+fn statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    // Dispatch Print
+    if peek_token(tokens).type_of == TokenType::Print {
+        let keyword = pop_token(tokens);
+        let expr = expression(tokens, errors)?;
+        let stmt = Stmt::Print(keyword, Box::new(expr));
+        try_consume(tokens, &[TokenType::Semicolon], "Couldn't find ';' at end of statement")?;
+        return Ok(stmt);
+    }
+
+    // Dispatch Blocked Statements
+    if consume_match(tokens, &[TokenType::LeftBrace]) {
+        Ok(Stmt::Block(block(tokens, errors)?))
+    } else if consume_match(tokens, &[TokenType::If]) {
+        if_statement(tokens, errors)
+    } else if consume_match(tokens, &[TokenType::While]) {
+        while_statement(tokens, errors)
+    } else if consume_match(tokens, &[TokenType::For]) {
+        for_statement(tokens, errors)
+    } else if peek_token(tokens).type_of == TokenType::Return {
+        return_statement(tokens, errors)
+    } else if peek_token(tokens).type_of == TokenType::Break {
+        let keyword = pop_token(tokens);
+        try_consume(tokens, &[TokenType::Semicolon], "Couldn't find ';' at end of statement")?;
+        Ok(Stmt::Break(keyword))
+    } else if peek_token(tokens).type_of == TokenType::Continue {
+        let keyword = pop_token(tokens);
+        try_consume(tokens, &[TokenType::Semicolon], "Couldn't find ';' at end of statement")?;
+        Ok(Stmt::Continue(keyword))
+    } else {
+        let expr = expression(tokens, errors)?;
+        let stmt = Stmt::Expr(Box::new(expr));
+        try_consume(tokens, &[TokenType::Semicolon], "Couldn't find ';' at end of statement")?;
+        Ok(stmt)
+    }
+}
+
+fn return_statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    let keyword = pop_token(tokens);
+    let mut value = Expr::Empty;
+    if peek_token(tokens).type_of != TokenType::Semicolon {
+        value = expression(tokens, errors)?;
+    }
+
+    try_consume(tokens, &[TokenType::Semicolon], "Expect ';' after return")?;
+    Ok(Stmt::Return(keyword, Box::new(value)))
+}
+
+fn for_statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    try_consume(tokens, &[TokenType::LeftParen], "Expect '(' after for")?;
+    if peek_type_at(tokens, 0) == TokenType::Var && peek_type_at(tokens, 2) == TokenType::In {
+        return for_in_statement(tokens, errors);
+    }
+
+    let initializer = if consume_match(tokens, &[TokenType::Semicolon]) {
+        Stmt::Expr(Box::new(Expr::Empty))
+    } else if consume_match(tokens, &[TokenType::Var]) {
+        var_declaration(tokens, errors)?
+    } else {
+        Stmt::Expr(Box::new(expression(tokens, errors)?))
+    };
+
+
+    let condition = if peek_token(tokens).type_of == TokenType::Semicolon {
+        Expr::Literal(Literal::Bool(true))
+    } else {
+        expression(tokens, errors)?
+    };
+    try_consume(tokens, &[TokenType::Semicolon], "Expect ';' after loop")?;
+
+    let increment = if peek_token(tokens).type_of == TokenType::RightParen {
+        Expr::Empty
+    } else {
+        expression(tokens, errors)?
+    };
+    try_consume(tokens, &[TokenType::RightParen], "Expect ')' after for")?;
+
+    let body = statement(tokens, errors)?;
+    let mut body = Stmt::While(Box::new(condition), Box::new(body), Box::new(increment));
+
+    if initializer != Stmt::Expr(Box::new(Expr::Empty)) {
+        body = Stmt::Block(vec![
+            initializer,
+            body,
+        ]);
+    }
+
+    Ok(body)
+}
+
+fn for_in_statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    pop_token(tokens);
+    let name = try_consume(tokens, &[TokenType::Identifier], "Expect variable name")?;
+    pop_token(tokens);
+    let iterable = expression(tokens, errors)?;
+    try_consume(tokens, &[TokenType::RightParen], "Expect ')' after for")?;
+    let body = statement(tokens, errors)?;
+
+    Ok(Stmt::ForIn(name, Box::new(iterable), Box::new(body)))
+}
+
+fn while_statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    try_consume(tokens, &[TokenType::LeftParen], "Expect '(' after while")?;
+    let condition = expression(tokens, errors)?;
+    try_consume(tokens, &[TokenType::RightParen], "Expect ')' after while")?;
+    let body = statement(tokens, errors)?;
+
+    Ok(Stmt::While(Box::new(condition), Box::new(body), Box::new(Expr::Empty)))
+}
+
+fn if_statement(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Stmt, LoxError> {
+    try_consume(tokens, &[TokenType::LeftParen], "Expect '(' after if")?;
+    let condition = expression(tokens, errors)?;
+    try_consume(tokens, &[TokenType::RightParen], "Expect ')' after condition")?;
+
+    let then_branch = statement(tokens, errors)?;
+    let else_branch = if consume_match(tokens, &[TokenType::Else]) {
+        statement(tokens, errors)?
+    } else {
+        Stmt::Expr(Box::new(Expr::Empty))
+    };
+
+    Ok(Stmt::If(Box::new(condition), Box::new(then_branch), Box::new(else_branch)))
+}
+
+fn block(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Vec<Stmt>, LoxError> {
+    let mut statements = Vec::new();
+
+    while peek_token(tokens).type_of != TokenType::RightBrace {
+        if peek_token(tokens).type_of == TokenType::Eof {
+            return Err(LoxError::parse(&peek_token(tokens), "Could not find matching '}'"));
+        }
+
+        if let Some(statement) = declaration(tokens, errors) {
+            statements.push(statement);
+        }
+    }
+
+    consume_until_found(tokens, &[TokenType::RightBrace]);
+
+    Ok(statements)
+}
+
+fn expression(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    assignment(tokens, errors)
+}
+
+fn assignment(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let expr = or(tokens, errors)?;
+
+    let equals = peek_token(tokens);
+    if consume_match(tokens, &[TokenType::Equal]) {
+        let value = assignment(tokens, errors)?;
+
+        // TODO: The left always needs to be an l-value. If the left is an
+        //  r-value, then it needs to be converted for assignment to work.
+
+        match expr {
+            Expr::Variable(token, _) => return Ok(Expr::Assign(token, Box::new(value), None)),
+            Expr::Get(object, name) => return Ok(Expr::Set(object, name, Box::new(value))),
+            Expr::Index(object, bracket, index) => {
+                return Ok(Expr::SetIndex(object, bracket, index, Box::new(value)))
+            }
+            _ => return Err(LoxError::parse(&equals, "Invalid assignment target")),
+        }
+    }
+
+    Ok(expr)
+}
+
+fn or(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = and(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, &[TokenType::Or]) {
+        let right = and(tokens, errors)?;
+        expr = Expr::Logical(Box::new(expr.clone()), token.clone(), Box::new(right.clone()));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn and(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = equality(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, &[TokenType::And]) {
+        let right = equality(tokens, errors)?;
+        expr = Expr::Logical(Box::new(expr.clone()), token.clone(), Box::new(right.clone()));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn equality(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = comparison(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, EQUALITY_OPS) {
+        let right = comparison(tokens, errors)?;
+        expr = Expr::Binary(Box::new(expr.clone()), token.clone(), Box::new(right));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn comparison(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = addition(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, COMPARISON_OPS) {
+        let right = addition(tokens, errors)?;
+        expr = Expr::Binary(Box::new(expr.clone()), token.clone(), Box::new(right));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn addition(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = multiplication(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, ADDITION_OPS) {
+        let right = multiplication(tokens, errors)?;
+        expr = Expr::Binary(Box::new(expr.clone()), token.clone(), Box::new(right));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn multiplication(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = unary(tokens, errors)?;
+    let mut token = peek_token(tokens);
+
+    while consume_match(tokens, MULTIPLICATION_OPS) {
+        let right = unary(tokens, errors)?;
+        expr = Expr::Binary(Box::new(expr.clone()), token.clone(), Box::new(right));
+        token = peek_token(tokens);
+    }
+
+    Ok(expr)
+}
+
+fn unary(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let token = peek_token(tokens);
+
+    if consume_match(tokens, UNARY_OPS) {
+        Ok(Expr::Unary(token.clone(), Box::new(expression(tokens, errors)?)))
+    } else {
+        call(tokens, errors)
+    }
+}
+
+fn call(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let mut expr = primary(tokens, errors)?;
+
+    loop {
+        if consume_match(tokens, &[TokenType::LeftParen]) {
+            expr = finish_call(tokens, errors, expr.clone())?;
+        } else if consume_match(tokens, &[TokenType::Dot]) {
+            let name = try_consume(tokens, &[TokenType::Identifier], "Expect property name after '.'")?;
+            expr = Expr::Get(Box::new(expr), name);
+        } else if peek_token(tokens).type_of == TokenType::LeftBracket {
+            let bracket = pop_token(tokens);
+            expr = subscript(tokens, errors, expr, bracket)?;
+        } else {
+            break;
+        }
+    }
+
+    Ok(expr)
+}
+
+fn finish_call(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, callee: Expr) -> Result<Expr, LoxError> {
+    let mut arguments = Vec::new();
+    if peek_token(tokens).type_of != TokenType::RightParen {
+        loop {
+            arguments.push(expression(tokens, errors)?);
+            if !consume_match(tokens, &[TokenType::Comma]) {
+                break;
+            }
+        }
+    }
+
+    let token = peek_token(tokens);
+    if arguments.len() > MAX_ARGUMENTS {
+        return Err(LoxError::parse(&token, "Can't have more than 255 arguments"));
+    }
+
+    try_consume(tokens, &[TokenType::RightParen], "Expect ')' after arguments")?;
+    Ok(Expr::Call(Box::new(callee), token, arguments))
+}
+
+// `[index]`, or `[start:end]` where either bound may be left out
+fn subscript(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, object: Expr, bracket: Token) -> Result<Expr, LoxError> {
+    let start = if peek_token(tokens).type_of == TokenType::Colon {
+        Expr::Empty
+    } else {
+        expression(tokens, errors)?
+    };
+
+    if !consume_match(tokens, &[TokenType::Colon]) {
+        try_consume(tokens, &[TokenType::RightBracket], "Expect ']' after index")?;
+        return Ok(Expr::Index(Box::new(object), bracket, Box::new(start)));
+    }
+
+    let end = if peek_token(tokens).type_of == TokenType::RightBracket {
+        Expr::Empty
+    } else {
+        expression(tokens, errors)?
+    };
+    try_consume(tokens, &[TokenType::RightBracket], "Expect ']' after slice")?;
+    Ok(Expr::Slice(Box::new(object), bracket, Box::new(start), Box::new(end)))
+}
+
+fn list(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, bracket: Token) -> Result<Expr, LoxError> {
+    let mut elements = Vec::new();
+    if peek_token(tokens).type_of != TokenType::RightBracket {
+        loop {
+            elements.push(expression(tokens, errors)?);
+            if !consume_match(tokens, &[TokenType::Comma]) {
+                break;
+            }
+        }
+    }
+
+    try_consume(tokens, &[TokenType::RightBracket], "Expect ']' after list elements")?;
+    Ok(Expr::List(bracket, elements))
+}
+
+// Statements never reach here, so a '{' in an expression always starts a map. One at the
+// start of a statement is still a block.
+fn map(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, brace: Token) -> Result<Expr, LoxError> {
+    let mut entries = Vec::new();
+    if peek_token(tokens).type_of != TokenType::RightBrace {
+        loop {
+            let key = expression(tokens, errors)?;
+            try_consume(tokens, &[TokenType::Colon], "Expect ':' after map key")?;
+            entries.push((key, expression(tokens, errors)?));
+            if !consume_match(tokens, &[TokenType::Comma]) {
+                break;
+            }
+        }
+    }
+
+    try_consume(tokens, &[TokenType::RightBrace], "Expect '}' after map entries")?;
+    Ok(Expr::Map(brace, entries))
+}
+
+fn primary(tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>) -> Result<Expr, LoxError> {
+    let token = pop_token(tokens);
+    let expr = match token.type_of {
+        TokenType::Number => Expr::Literal(token.literal),
+        TokenType::String => Expr::Literal(token.literal),
+        TokenType::False => Expr::Literal(Literal::Bool(false)),
+        TokenType::True => Expr::Literal(Literal::Bool(true)),
+        TokenType::Nil => Expr::Literal(Literal::Nil),
+        TokenType::Fun => lambda(token, tokens, errors)?,
+        TokenType::LeftBracket => list(tokens, errors, token)?,
+        TokenType::LeftBrace => map(tokens, errors, token)?,
+        TokenType::LeftParen if is_arrow_function(tokens) => arrow_function(token, tokens, errors)?,
+        TokenType::LeftParen => {
+            let expr = Expr::Grouping(Box::new(expression(tokens, errors)?));
+            try_consume(tokens, &[TokenType::RightParen], "Expect ')' after expression")?;
+            expr
+        }
+        TokenType::Identifier => Expr::Variable(token, None),
+        TokenType::This => Expr::This(token, 0),
+        TokenType::Super => {
+            try_consume(tokens, &[TokenType::Dot], "Expect '.' after 'super'")?;
+            let method = try_consume(tokens, &[TokenType::Identifier], "Expect superclass method name")?;
+            Expr::Super(token, method, 0)
+        }
+        _ => {
+            tokens.push(token.clone());
+            return Err(LoxError::parse(&token, "Expect expression"));
+        }
+    };
+    Ok(expr)
+}
@@ -0,0 +1,292 @@
+use crate::common::token::Token;
+use crate::common::expression::Expr;
+use crate::common::statement::Stmt;
+use crate::common::error::LoxError;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+// Whether the resolver is inside a class, and whether that class has a superclass
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// Each scope maps a local's name to whether its initializer has finished resolving
+struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    function_type: FunctionType,
+    class_type: ClassType,
+    // Loops enclosing the current statement within the current function
+    loop_depth: usize,
+    errors: Vec<LoxError>,
+}
+
+// Binds every local variable reference to the number of scopes between it and its
+// declaration. References left unresolved (None) are globals.
+pub fn resolve(statements: &mut [Stmt]) -> Result<(), Vec<LoxError>> {
+    let mut resolver = Resolver {
+        scopes: Vec::new(),
+        function_type: FunctionType::None,
+        class_type: ClassType::None,
+        loop_depth: 0,
+        errors: Vec::new(),
+    };
+
+    resolver.resolve_statements(statements);
+
+    if resolver.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+impl Resolver {
+    fn resolve_statements(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &mut Stmt) {
+        match statement {
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::Var(name, initializer) => {
+                self.declare(name);
+                self.resolve_expression(initializer);
+                self.define(name);
+            }
+            Stmt::Function(name, parameters, body) => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(parameters, body, FunctionType::Function);
+            }
+            Stmt::Class(name, superclass, methods) => {
+                let enclosing_class_type = self.class_type;
+                self.class_type = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+                if let Some(superclass) = superclass {
+                    self.class_type = ClassType::Subclass;
+                    self.resolve_expression(superclass);
+                }
+                for method in methods {
+                    if let Stmt::Function(method_name, parameters, body) = method {
+                        let function_type = if method_name.lexeme == "init" {
+                            FunctionType::Initializer
+                        } else {
+                            FunctionType::Method
+                        };
+                        self.resolve_function(parameters, body, function_type);
+                    }
+                }
+                self.class_type = enclosing_class_type;
+            }
+            Stmt::Expr(expr) => self.resolve_expression(expr),
+            Stmt::Print(_, expr) => self.resolve_expression(expr),
+            Stmt::Return(keyword, value) => {
+                if self.function_type == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code");
+                }
+                // An initializer always returns this, so only a bare return is allowed
+                if self.function_type == FunctionType::Initializer && **value != Expr::Empty {
+                    self.error(keyword, "Can't return a value from an initializer");
+                }
+                self.resolve_expression(value);
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                self.resolve_statement(else_branch);
+            }
+            Stmt::While(condition, body, increment) => {
+                self.resolve_expression(condition);
+                self.loop_depth += 1;
+                self.resolve_statement(body);
+                self.loop_depth -= 1;
+                self.resolve_expression(increment);
+            }
+            // The loop variable gets a scope of its own, around the body
+            Stmt::ForIn(name, iterable, body) => {
+                self.resolve_expression(iterable);
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.loop_depth += 1;
+                self.resolve_statement(body);
+                self.loop_depth -= 1;
+                self.end_scope();
+            }
+            Stmt::Break(keyword) => {
+                if self.loop_depth == 0 {
+                    self.error(keyword, "Can't use 'break' outside of a loop");
+                }
+            }
+            Stmt::Continue(keyword) => {
+                if self.loop_depth == 0 {
+                    self.error(keyword, "Can't use 'continue' outside of a loop");
+                }
+            }
+        }
+    }
+
+    // Parameters and the function body share a single scope. A method's scope also holds
+    // this, and super in a subclass, as the interpreter binds them there.
+    fn resolve_function(&mut self, parameters: &[Token], body: &mut [Stmt], function_type: FunctionType) {
+        let enclosing_function_type = self.function_type;
+        let enclosing_loop_depth = self.loop_depth;
+        self.function_type = function_type;
+        self.loop_depth = 0;
+
+        self.begin_scope();
+        if matches!(function_type, FunctionType::Method | FunctionType::Initializer) {
+            self.define_keyword("this");
+            if self.class_type == ClassType::Subclass {
+                self.define_keyword("super");
+            }
+        }
+        for parameter in parameters {
+            self.declare(parameter);
+            self.define(parameter);
+        }
+        self.resolve_statements(body);
+        self.end_scope();
+
+        self.function_type = enclosing_function_type;
+        self.loop_depth = enclosing_loop_depth;
+    }
+
+    fn resolve_expression(&mut self, expression: &mut Expr) {
+        match expression {
+            Expr::Variable(name, depth) => {
+                let in_own_initializer = self.scopes.last()
+                    .and_then(|scope| scope.get(&name.lexeme)) == Some(&false);
+                if in_own_initializer {
+                    self.error(name, "Can't read local variable in its own initializer");
+                }
+                *depth = self.resolve_local(&name.lexeme);
+            }
+            Expr::Assign(name, value, depth) => {
+                self.resolve_expression(value);
+                *depth = self.resolve_local(&name.lexeme);
+            }
+            Expr::Binary(left, _, right) => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expr::Logical(left, _, right) => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expr::Call(callee, _, arguments) => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expr::Get(object, _) => self.resolve_expression(object),
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.resolve_expression(element);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expression(key);
+                    self.resolve_expression(value);
+                }
+            }
+            Expr::Index(object, _, index) => {
+                self.resolve_expression(object);
+                self.resolve_expression(index);
+            }
+            Expr::SetIndex(object, _, index, value) => {
+                self.resolve_expression(object);
+                self.resolve_expression(index);
+                self.resolve_expression(value);
+            }
+            Expr::Slice(object, _, start, end) => {
+                self.resolve_expression(object);
+                self.resolve_expression(start);
+                self.resolve_expression(end);
+            }
+            Expr::Set(object, _, value) => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expr::Unary(_, expr) => self.resolve_expression(expr),
+            Expr::Grouping(expr) => self.resolve_expression(expr),
+            Expr::Lambda(_, parameters, body) => {
+                self.resolve_function(parameters, body, FunctionType::Function);
+            }
+            Expr::This(keyword, depth) => {
+                if self.class_type == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class");
+                }
+                *depth = self.resolve_local("this").unwrap_or_default();
+            }
+            Expr::Super(keyword, _, depth) => {
+                match self.class_type {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class"),
+                    ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass"),
+                    ClassType::Subclass => {}
+                }
+                *depth = self.resolve_local("super").unwrap_or_default();
+            }
+            Expr::Literal(_) | Expr::Empty => {}
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.scopes.iter()
+            .rev()
+            .position(|scope| scope.contains_key(name))
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let already_declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexeme.clone(), false).is_some(),
+            None => false,
+        };
+        if already_declared {
+            self.error(name, "Already a variable with this name in this scope");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn define_keyword(&mut self, keyword: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(keyword.to_string(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(LoxError::resolve(token, message));
+    }
+}
@@ -1,10 +1,27 @@
-use crate::treewalk::token::{Token, token_type::TokenType};
+use crate::common::token::{Token, Span, token_type::TokenType};
+use crate::common::error::LoxError;
 use std::collections::HashMap;
 
 pub struct Scanner {
     source: String,
 }
 
+// Input that stops inside a string or a block comment isn't wrong yet; the REPL just
+// hasn't been given all of it. Keeping that apart from other errors lets it ask for more
+// without looking at the message.
+enum ScanError {
+    Invalid(String),
+    Unterminated(String),
+}
+
+impl ScanError {
+    fn into_error(self, span: Span) -> LoxError {
+        match self {
+            ScanError::Invalid(message) | ScanError::Unterminated(message) => LoxError::Scan(span, message),
+        }
+    }
+}
+
 use lazy_static;
 
 impl Scanner {
@@ -13,25 +30,64 @@ impl Scanner {
         Scanner { source }
     }
 
-    pub fn scan_tokens(&self) -> Vec<Token> {
+    pub fn scan_tokens(&self) -> Result<Vec<Token>, LoxError> {
+        self.scan().map_err(|(error, span)| error.into_error(span))
+    }
+
+    // A failed scan also returns the span from the start of the bad token to where the
+    // scanner gave up
+    fn scan(&self) -> Result<Vec<Token>, (ScanError, Span)> {
         let mut tokens = Vec::new();
         let mut line: u32 = 1;
 
         let mut source: Vec<char> = self.source.chars().collect();
         source.reverse();
 
+        // The byte offset of every character, and of the end of the source. How far the
+        // scanner has got is the number of characters no longer on the stack.
+        let offsets: Vec<usize> = self.source.char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(self.source.len()))
+            .collect();
+        let length = source.len();
+
         while let Some(c) = source.pop() {
-            if let Some(tok) = Scanner::scan_token(c, &mut source, &mut line) {
-                tokens.push(tok);
+            let start = offsets[length - source.len() - 1];
+            let start_line = line;
+            let scanned = Scanner::scan_token(c, &mut source, &mut line);
+            let end = offsets[length - source.len()];
+            match scanned {
+                Ok(Some(tok)) => {
+                    let span = self.span(tok.line(), start, end);
+                    tokens.push(tok.at(span));
+                }
+                Ok(None) => {}
+                Err(error) => return Err((error, self.span(start_line, start, end))),
             }
         }
 
-        tokens.push(Token::new(TokenType::Eof, "", line));
+        let end = self.source.len();
+        tokens.push(Token::new(TokenType::Eof, "", line).at(self.span(line, end, end)));
+
+        Ok(tokens)
+    }
 
-        tokens
+    fn span(&self, line: u32, start: usize, end: usize) -> Span {
+        let line_start = self.source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let column = self.source[line_start..start].chars().count() as u32 + 1;
+        Span { line, column, start, end }
+    }
+
+    // Unclosed brackets also mean the input carries on past the end of the line
+    pub fn needs_more_input(&self) -> bool {
+        match self.scan() {
+            Ok(tokens) => bracket_depth(&tokens) > 0,
+            Err((ScanError::Unterminated(_), _)) => true,
+            Err((ScanError::Invalid(_), _)) => false,
+        }
     }
 
-    fn scan_token(c: char, source: &mut Vec<char>, line: &mut u32) -> Option<Token> {
+    fn scan_token(c: char, source: &mut Vec<char>, line: &mut u32) -> Result<Option<Token>, ScanError> {
         let next_c = match source.pop() {
             Some(c) => {
                 source.push(c);
@@ -45,6 +101,9 @@ impl Scanner {
             ')' => Some(TokenType::RightParen),
             '{' => Some(TokenType::LeftBrace),
             '}' => Some(TokenType::RightBrace),
+            '[' => Some(TokenType::LeftBracket),
+            ']' => Some(TokenType::RightBracket),
+            ':' => Some(TokenType::Colon),
             ',' => Some(TokenType::Comma),
             '.' => Some(TokenType::Dot),
             '-' => Some(TokenType::Minus),
@@ -54,6 +113,7 @@ impl Scanner {
             '!' if next_c == '=' => Some(TokenType::BangEqual),
             '!' => Some(TokenType::Bang),
             '=' if next_c == '=' => Some(TokenType::EqualEqual),
+            '=' if next_c == '>' => Some(TokenType::Arrow),
             '=' => Some(TokenType::Equal),
             '<' if next_c == '=' => Some(TokenType::LessEqual),
             '<' => Some(TokenType::Less),
@@ -63,11 +123,11 @@ impl Scanner {
             // Inline and Block Comment
             '/' if next_c == '/' => {
                 eat_line(source);
-                return None;
+                return Ok(None);
             }
             '/' if next_c == '*' => {
-                eat_block_comment(source);
-                return None;
+                eat_block_comment(source, line)?;
+                return Ok(None);
             }
 
             '/' => Some(TokenType::Slash),
@@ -83,90 +143,106 @@ impl Scanner {
 
             // String Literals
             '"' => {
-                let literal = eat_string(source);
-                return Some(Token::new_string(&literal, *line));
+                let start_line = *line;
+                let literal = eat_string(source, line)?;
+                return Ok(Some(Token::new_string(&literal, start_line)));
             }
 
             // Number Literals
-            '0'...'9' => {
+            '0'..='9' => {
                 source.push(c);
                 let literal = eat_number(source);
-                return Some(Token::new_number(literal, *line));
+                return Ok(Some(Token::new_number(literal, *line)));
             }
 
             // Alphabetic words
-            'a'...'z' | 'A'...'Z' => {
+            'a'..='z' | 'A'..='Z' => {
                 source.push(c);
                 let lexeme = eat_identifier(source);
 
                 match KEYWORDS.get(&lexeme) {
                     Some(type_of) => {
                         let type_of = *type_of;
-                        return Some(Token::new_keyword(type_of, *line));
+                        return Ok(Some(Token::new_keyword(type_of, *line)));
                     }
                     None => {
-                        return Some(Token::new_identifier(&lexeme, *line));
+                        return Ok(Some(Token::new_identifier(&lexeme, *line)));
                     }
                 }
             }
 
             // Couldn't Match
             _ => {
-                eprintln!("Unexpected character {}", c);
-                return None;
+                let message = format!("Unexpected character '{}'", c);
+                return Err(ScanError::Invalid(message));
             }
         };
 
         match token_type {
             Some(TokenType::BangEqual) => { source.pop(); }
             Some(TokenType::EqualEqual) => { source.pop(); }
+            Some(TokenType::Arrow) => { source.pop(); }
             Some(TokenType::LessEqual) => { source.pop(); }
             Some(TokenType::GreaterEqual) => { source.pop(); }
             _ => {}
         }
 
-        match token_type {
-            Some(type_of) => Some(Token::new(type_of, "", *line)),
-            None => None,
-        }
+        Ok(token_type.map(|type_of| Token::new(type_of, "", *line)))
     }
 }
 
-// Consumes the vec until a new line is found
+// Counts brackets still open at the end of the tokens. Stray closing brackets are left
+// for the parser to report.
+fn bracket_depth(tokens: &[Token]) -> i32 {
+    tokens.iter().fold(0, |depth, token| match token.type_of {
+        TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => depth + 1,
+        TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => depth - 1,
+        _ => depth,
+    })
+}
+
+// Consumes the vec until a new line is found, leaving the new line to be counted
 fn eat_line(source: &mut Vec<char>) {
     while let Some(c) = source.pop() {
         if c == '\n' {
+            source.push(c);
             return;
         }
     }
 }
 
-fn eat_block_comment(source: &mut Vec<char>) {
+fn eat_block_comment(source: &mut Vec<char>, line: &mut u32) -> Result<(), ScanError> {
     let mut last_seen = '/';
 
     while let Some(c) = source.pop() {
-        println!("{} {}", last_seen, c);
         if c == '/' && last_seen == '*' {
-            return;
+            return Ok(());
+        }
+        if c == '\n' {
+            *line += 1;
         }
         last_seen = c;
     }
+
+    Err(ScanError::Unterminated("Unterminated block comment".to_string()))
 }
 
 
 // Scan until another quotation mark is found or end of stack
-fn eat_string(source: &mut Vec<char>) -> String {
+fn eat_string(source: &mut Vec<char>, line: &mut u32) -> Result<String, ScanError> {
     let mut literal = String::new();
 
     while let Some(c) = source.pop() {
         if c == '"' {
-            return literal;
-        } else {
-            literal.push(c);
+            return Ok(literal);
         }
+        if c == '\n' {
+            *line += 1;
+        }
+        literal.push(c);
     }
 
-    literal
+    Err(ScanError::Unterminated("Unterminated string".to_string()))
 }
 
 // Consume until a non-integer character is found
@@ -175,7 +251,7 @@ fn eat_number(source: &mut Vec<char>) -> f64 {
     let mut dot_count = 0;
 
     while let Some(c) = source.pop() {
-        if c.is_digit(10) {
+        if c.is_ascii_digit() {
             literal.push(c);
         } else if c == '.' && dot_count == 0 {
             dot_count += 1;
@@ -206,15 +282,17 @@ fn eat_identifier(source: &mut Vec<char>) -> String {
 }
 
 lazy_static! {
-    static ref KEYWORDS: HashMap<String, TokenType> = {
+    pub(crate) static ref KEYWORDS: HashMap<String, TokenType> = {
         let mut m = HashMap::new();
         m.insert("and".to_string(), TokenType::And);
         m.insert("break".to_string(), TokenType::Break);
         m.insert("class".to_string(), TokenType::Class);
+        m.insert("continue".to_string(), TokenType::Continue);
         m.insert("else".to_string(), TokenType::Else);
         m.insert("for".to_string(), TokenType::For);
         m.insert("fun".to_string(), TokenType::Fun);
         m.insert("if".to_string(), TokenType::If);
+        m.insert("in".to_string(), TokenType::In);
         m.insert("nil".to_string(), TokenType::Nil);
         m.insert("or".to_string(), TokenType::Or);
         m.insert("print".to_string(), TokenType::Print);
@@ -0,0 +1,20 @@
+use crate::common::token::{Token};
+use crate::common::expression::Expr;
+
+#[derive(Debug, Clone, PartialOrd, PartialEq)]
+pub enum Stmt {
+    Block(Vec<Stmt>),
+    Expr(Box<Expr>),
+    Print(Token, Box<Expr>),
+    Return(Token, Box<Expr>),
+    Var(Token, Box<Expr>),
+    If(Box<Expr>, Box<Stmt>, Box<Stmt>),
+    // The last expression is a `for` loop's increment, run after the body and on `continue`
+    While(Box<Expr>, Box<Stmt>, Box<Expr>),
+    // `for (var name in iterable) body`
+    ForIn(Token, Box<Expr>, Box<Stmt>),
+    Function(Token, Vec<Token>, Vec<Stmt>),
+    Class(Token, Option<Box<Expr>>, Vec<Stmt>),
+    Break(Token),
+    Continue(Token),
+}
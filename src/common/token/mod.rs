@@ -0,0 +1,135 @@
+pub mod token_type;
+
+#[derive(PartialOrd, PartialEq, Clone)]
+pub struct Token {
+    pub type_of: token_type::TokenType,
+    pub lexeme: String,
+    pub literal: Literal,
+    span: Span,
+}
+
+// Where a token was found. Columns count characters from 1 and `start..end` is the token's
+// byte range in the source. Tokens made up by the interpreter only know their line.
+#[derive(Debug, Default, PartialOrd, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // A position known only by its line
+    pub fn on_line(line: u32) -> Span {
+        Span { line, ..Span::default() }
+    }
+
+    // Where tokens made up for the host are, rather than anywhere in the source. Errors
+    // found there are reported without a position.
+    pub fn host() -> Span {
+        Span::default()
+    }
+}
+
+// The value a token or literal expression stands for. Each backend turns it into a value
+// of its own.
+#[derive(PartialOrd, PartialEq, Clone)]
+pub enum Literal {
+    // The literal of a token that has none
+    None,
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Nil,
+}
+
+impl Token {
+    pub fn new(type_of: token_type::TokenType, lexeme: &str, line: u32) -> Token {
+        let literal = Literal::None;
+        let lexeme = lexeme.to_string();
+        let span = Span { line, ..Span::default() };
+        Token { type_of, lexeme, literal, span }
+    }
+
+    pub fn new_string(literal: &str, line: u32) -> Token {
+        let type_of = token_type::TokenType::String;
+        let literal = Literal::String(literal.to_string());
+        let lexeme = String::new();
+        let span = Span { line, ..Span::default() };
+        Token { type_of, literal, lexeme, span }
+    }
+
+    pub fn new_number(literal: f64, line: u32) -> Token {
+        let type_of = token_type::TokenType::Number;
+        let literal = Literal::Number(literal);
+        let lexeme = String::new();
+        let span = Span { line, ..Span::default() };
+        Token { type_of, literal, lexeme, span }
+    }
+
+    pub fn new_identifier(lexeme: &str, line: u32) -> Token {
+        let type_of = token_type::TokenType::Identifier;
+        let literal = Literal::None;
+        let lexeme = lexeme.to_string();
+        let span = Span { line, ..Span::default() };
+        Token { type_of, lexeme, literal, span }
+    }
+
+    pub fn new_keyword(type_of: token_type::TokenType, line: u32) -> Token {
+        let literal = Literal::None;
+        let lexeme = String::new();
+        let span = Span { line, ..Span::default() };
+        Token { type_of, lexeme, literal, span }
+    }
+
+    // Places the token in the source, replacing the line it was created with
+    pub fn at(mut self, span: Span) -> Token {
+        self.span = span;
+        self
+    }
+
+    pub fn line(&self) -> u32 {
+        self.span.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.span.column
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    // The token as it was written in the source
+    pub fn text(&self) -> String {
+        match &self.literal {
+            Literal::String(string) => format!("\"{}\"", string),
+            Literal::Number(number) => format!("{}", number),
+            _ if !self.lexeme.is_empty() => self.lexeme.clone(),
+            _ => self.type_of.spelling().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.literal {
+            Literal::None if !self.lexeme.is_empty() => write!(f, "{}", self.lexeme),
+            Literal::None => write!(f, "Token::{:?}", self.type_of),
+            literal => write!(f, "{:?}", literal),
+        }
+    }
+}
+
+impl std::fmt::Debug for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Literal::None => write!(f, "None"),
+            Literal::String(s) => write!(f, "\"{}\"", s),
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Bool(true) => write!(f, "true"),
+            Literal::Bool(false) => write!(f, "false"),
+            Literal::Nil => write!(f, "nil"),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+#[cfg(feature = "trace")]
+use std::cell::RefCell;
+use std::io::Write;
+#[cfg(feature = "trace")]
+use std::rc::Rc;
+
+pub const TRACE_AVAILABLE: bool = cfg!(feature = "trace");
+
+// Diagnostic channel for execution traces, kept apart from program output. Tracing is
+// only compiled in with the `trace` feature, and even then stays off until enabled.
+// Clones share one writer, like Output.
+#[derive(Clone, Default)]
+pub struct Trace {
+    #[cfg(feature = "trace")]
+    writer: Rc<RefCell<Option<Box<dyn Write>>>>,
+}
+
+impl Trace {
+    #[cfg(feature = "trace")]
+    pub fn enable(&self, writer: Box<dyn Write>) {
+        *self.writer.borrow_mut() = Some(writer);
+    }
+
+    #[cfg(not(feature = "trace"))]
+    pub fn enable(&self, _writer: Box<dyn Write>) {}
+
+    // The event is only formatted when tracing is on. A trace that can't be written is
+    // dropped rather than failing the program.
+    #[cfg(feature = "trace")]
+    pub fn event<F: FnOnce() -> String>(&self, event: F) {
+        if let Some(writer) = self.writer.borrow_mut().as_mut() {
+            let _ = writeln!(writer, "[trace] {}", event());
+        }
+    }
+
+    #[cfg(not(feature = "trace"))]
+    pub fn event<F: FnOnce() -> String>(&self, _event: F) {}
+}
@@ -1,4 +1,7 @@
-//pub mod treewalk;
+pub mod common;
+pub mod treewalk;
+pub mod bytecode;
+mod repl;
 
 #[macro_use]
 extern crate lazy_static;
@@ -1,14 +1,45 @@
+use rlox::bytecode::VM;
+use rlox::common::TRACE_AVAILABLE;
+use rlox::treewalk::Interpreter;
 use std::env;
-
-use rlox;
+use std::io;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
+    let gc_stress = flag("--gc-stress");
+    let use_vm = flag("--vm") || gc_stress;
+    let disassemble = flag("--disassemble");
+    let trace = flag("--trace");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+
+    if trace && !TRACE_AVAILABLE {
+        eprintln!("--trace needs rlox built with the \"trace\" feature");
+    }
+
+    match (paths.len(), use_vm, disassemble) {
+        (1, _, true) => rlox::bytecode::disassemble_file(paths[0]),
+        (0, false, false) => rlox::treewalk::run_prompt(interpreter(trace)),
+        (1, false, false) => rlox::treewalk::run_file(paths[0], interpreter(trace)),
+        (0, true, false) => rlox::bytecode::run_prompt(vm(gc_stress, trace)),
+        (1, true, false) => rlox::bytecode::run_file(paths[0], vm(gc_stress, trace)),
+        _ => eprintln!("Usage: rlox [--vm] [--gc-stress] [--trace] [--disassemble] [source]"),
+    }
+}
 
-    match args.len() {
-        1 => rlox::treewalk::run_prompt(),
-        2 => rlox::treewalk::run_file(&args[1]),
-        _ => eprintln!("Usage: rlox [source]"),
+fn interpreter(trace: bool) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    if trace {
+        interpreter.set_trace(Box::new(io::stderr()));
     }
+    interpreter
 }
 
+fn vm(gc_stress: bool, trace: bool) -> VM {
+    let mut vm = VM::new();
+    vm.set_gc_stress(gc_stress);
+    if trace {
+        vm.set_trace(Box::new(io::stderr()));
+    }
+    vm
+}
@@ -0,0 +1,250 @@
+use crate::common::LoxError;
+use crate::common::parser;
+use crate::common::scanner::{Scanner, KEYWORDS};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+const COMMANDS: &str = ":tokens <source>, :ast <source>, :env, :load <path>, :reset, :quit";
+
+// What the REPL needs from an execution backend
+pub(crate) trait Session {
+    fn run(&mut self, source: &str, is_repl: bool) -> Result<(), Vec<LoxError>>;
+
+    // Names bound in the global scope with their printed values, sorted by name
+    fn bindings(&self) -> Vec<(String, String)>;
+
+    fn reset(&mut self);
+}
+
+// Completes keywords and the names bound in the session's global scope
+#[derive(Default)]
+struct LoxHelper {
+    globals: Vec<String>,
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = word_start(&line[..pos]);
+        Ok((start, complete_word(&line[start..pos], &self.globals)))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+// The byte offset of the word the line ends with. The character before it can be more
+// than one byte long.
+fn word_start(line: &str) -> usize {
+    line.char_indices()
+        .rfind(|(_, c)| !c.is_alphanumeric())
+        .map_or(0, |(index, c)| index + c.len_utf8())
+}
+
+fn complete_word(prefix: &str, globals: &[String]) -> Vec<String> {
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<String> = KEYWORDS.keys()
+        .chain(globals.iter())
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+// Wraps the line editor, which provides cursor movement, history and reverse search (Ctrl-R)
+struct LineReader {
+    editor: Editor<LoxHelper, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl LineReader {
+    fn new() -> LineReader {
+        let mut editor = Editor::new().expect("Couldn't start the line editor");
+        editor.set_helper(Some(LoxHelper::default()));
+
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"));
+        if let Some(path) = &history {
+            // A missing history file just means this is the first session
+            let _ = editor.load_history(path);
+        }
+        LineReader { editor, history }
+    }
+
+    // Reads lines until they form complete input, showing a continuation prompt while a
+    // bracket, string or block comment is still open. Returns None at end of input.
+    fn read_input(&mut self, globals: Vec<String>) -> Option<String> {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.globals = globals;
+        }
+
+        let mut input = String::new();
+        let mut prompt = "> ";
+        loop {
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    input.push_str(&line);
+                    input.push('\n');
+                }
+                // Ctrl-C abandons whatever has been typed so far
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    prompt = "> ";
+                    continue;
+                }
+                Err(_) if input.is_empty() => return None,
+                Err(_) => break,
+            }
+
+            if input.trim_start().starts_with(':') || !Scanner::new(&input).needs_more_input() {
+                break;
+            }
+            prompt = ". ";
+        }
+
+        self.remember(input.trim_end());
+        Some(input)
+    }
+
+    fn remember(&mut self, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        let _ = self.editor.add_history_entry(entry);
+        if let Some(path) = &self.history {
+            if let Err(error) = self.editor.save_history(path) {
+                eprintln!("Couldn't save history to '{}': {}", path.display(), error);
+            }
+        }
+    }
+}
+
+// Lines starting with `:` are meta-commands rather than Lox
+pub(crate) fn run_prompt(session: &mut dyn Session) {
+    let mut reader = LineReader::new();
+    loop {
+        let globals = session.bindings().into_iter().map(|(name, _)| name).collect();
+        let input = match reader.read_input(globals) {
+            Some(input) => input,
+            None => break,
+        };
+
+        let line = input.trim();
+        let result = if line.starts_with(':') {
+            match meta_command(session, line) {
+                Some(result) => result,
+                None => return,
+            }
+        } else {
+            session.run(&input, true)
+        };
+
+        if let Err(errors) = result {
+            report(&errors);
+        }
+        io::stdout().flush().unwrap();
+    }
+    println!();
+}
+
+// Returns None when the session should end
+fn meta_command(session: &mut dyn Session, line: &str) -> Option<Result<(), Vec<LoxError>>> {
+    let (command, argument) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+
+    let result = match command {
+        ":tokens" => Scanner::new(argument).scan_tokens()
+            .map(|tokens| {
+                for token in tokens {
+                    println!("{:?} {:?}", token.type_of, token);
+                }
+            })
+            .map_err(|error| vec![error]),
+        ":ast" => Scanner::new(argument).scan_tokens()
+            .map_err(|error| vec![error])
+            .and_then(|mut tokens| parser::parse(&mut tokens))
+            .map(|statements| {
+                for statement in statements {
+                    println!("{:?}", statement);
+                }
+            }),
+        ":env" => {
+            for (name, value) in session.bindings() {
+                println!("{} = {}", name, value);
+            }
+            Ok(())
+        }
+        ":load" => match fs::read_to_string(argument) {
+            Ok(contents) => session.run(&contents, false),
+            Err(error) => {
+                eprintln!("Couldn't open '{}': {}", argument, error);
+                Ok(())
+            }
+        },
+        ":reset" => {
+            session.reset();
+            Ok(())
+        }
+        ":quit" => return None,
+        _ => {
+            eprintln!("Unknown command '{}'. Commands are {}", command, COMMANDS);
+            Ok(())
+        }
+    };
+    Some(result)
+}
+
+pub(crate) fn report(errors: &[LoxError]) {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_word, word_start};
+
+    #[test]
+    fn test_complete_keywords_and_globals() {
+        let globals = vec!["counter".to_string(), "total".to_string()];
+        assert_eq!(complete_word("cl", &globals), vec!["class"]);
+        assert_eq!(complete_word("co", &globals), vec!["continue", "counter"]);
+        assert_eq!(complete_word("t", &globals), vec!["this", "total", "true"]);
+        assert!(complete_word("", &globals).is_empty());
+    }
+
+    #[test]
+    fn test_complete_after_non_ascii() {
+        assert_eq!(word_start("print cou"), 6);
+        assert_eq!(word_start("cou"), 0);
+        let line = "print \"→\"+cou";
+        let start = word_start(line);
+        assert_eq!(&line[start..], "cou");
+        assert_eq!(word_start("print «"), "print «".len());
+        assert_eq!(&"var café"[word_start("var café")..], "café");
+    }
+}
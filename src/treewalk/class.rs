@@ -0,0 +1,106 @@
+use crate::treewalk::object::Object;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Object>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    fields: HashMap<String, Object>,
+}
+
+impl Class {
+    pub fn new(name: &str, superclass: Option<Rc<Class>>, methods: HashMap<String, Object>) -> Class {
+        let name = name.to_string();
+        Class { name, superclass, methods }
+    }
+
+    // Walks up the inheritance chain. Along with the method, returns the superclass of
+    // the class that declared it, which is what `super` refers to inside that method.
+    pub fn find_method(&self, name: &str) -> Option<(Object, Option<Rc<Class>>)> {
+        match self.methods.get(name) {
+            Some(method) => Some((method.clone(), self.superclass.clone())),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    // Whether the method is the init of this class or one of its superclasses. Functions
+    // are compared by identity, so a method only matches the declaration it came from.
+    pub fn is_initializer(&self, method: &Object) -> bool {
+        let declared = match (self.methods.get("init"), method) {
+            (Some(Object::Function(_, init, _)), Object::Function(_, body, _)) => Rc::ptr_eq(init, body),
+            _ => false,
+        };
+        declared || self.superclass.as_ref().is_some_and(|superclass| superclass.is_initializer(method))
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = &Object> {
+        self.methods.values()
+    }
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Instance {
+        let fields = HashMap::new();
+        Instance { class, fields }
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<Object> {
+        self.fields.get(name).cloned()
+    }
+
+    pub fn set_field(&mut self, name: String, value: Object) {
+        self.fields.insert(name, value);
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &Object> {
+        self.fields.values()
+    }
+
+    pub fn take_fields(&mut self) -> HashMap<String, Object> {
+        std::mem::take(&mut self.fields)
+    }
+}
+
+// Classes and instances have identity: two are only equal if they are the same object
+impl PartialEq for Class {
+    fn eq(&self, other: &Class) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Class {
+    fn partial_cmp(&self, _other: &Class) -> Option<Ordering> {
+        None
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Instance) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Instance {
+    fn partial_cmp(&self, _other: &Instance) -> Option<Ordering> {
+        None
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -0,0 +1,266 @@
+use crate::treewalk::class::{Class, Instance};
+use crate::treewalk::environment::Enclosure;
+use crate::treewalk::object::{Object, ObjectMap};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+const INITIAL_THRESHOLD: usize = 1024;
+const DEFAULT_GROWTH_FACTOR: usize = 2;
+
+// Reference counting frees most scopes and instances as soon as they're unreachable, but
+// not cycles: a closure stored in the scope it captures, or an instance whose fields lead
+// back to it, keeps its own count up. The collector keeps a weak handle to every scope and
+// instance and looks for groups of objects that are only referred to by each other.
+//
+// Everything reachable from the tracked objects is a candidate. Each candidate's strong
+// count, less the references the candidates hold to it, is the number of references from
+// outside: the globals, a running call, or a value the host kept. Candidates with outside
+// references are live, and so is everything they reach. The rest are emptied, which drops
+// the references that held their cycles together.
+#[derive(Clone, Default)]
+pub struct Collector {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    scopes: Vec<Weak<RefCell<HashMap<String, Object>>>>,
+    instances: Vec<Weak<RefCell<Instance>>>,
+    tracked_since_collection: usize,
+    next_collection: usize,
+    next_prune: usize,
+    growth_factor: usize,
+}
+
+impl Default for State {
+    fn default() -> State {
+        State {
+            scopes: Vec::new(),
+            instances: Vec::new(),
+            tracked_since_collection: 0,
+            next_collection: INITIAL_THRESHOLD,
+            next_prune: INITIAL_THRESHOLD,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+        }
+    }
+}
+
+impl Collector {
+    pub fn track_scope(&self, scope: &Enclosure) {
+        let mut state = self.state.borrow_mut();
+        state.scopes.push(Rc::downgrade(scope));
+        state.tracked();
+    }
+
+    pub fn track_instance(&self, instance: &Rc<RefCell<Instance>>) {
+        let mut state = self.state.borrow_mut();
+        state.instances.push(Rc::downgrade(instance));
+        state.tracked();
+    }
+
+    // After a collection the next one is due once this many times the number of live
+    // tracked objects have been created
+    pub fn set_growth_factor(&self, growth_factor: usize) {
+        self.state.borrow_mut().growth_factor = growth_factor.max(1);
+    }
+
+    pub fn should_collect(&self) -> bool {
+        let state = self.state.borrow();
+        state.tracked_since_collection >= state.next_collection
+    }
+
+    // Empties every candidate that nothing outside the candidates refers to, directly or
+    // through other candidates. Returns how many were emptied.
+    pub fn collect(&self) -> usize {
+        let seeds: Vec<Node> = {
+            let mut state = self.state.borrow_mut();
+            state.prune();
+            let scopes = state.scopes.iter().filter_map(Weak::upgrade).map(Node::Scope);
+            let instances = state.instances.iter().filter_map(Weak::upgrade).map(Node::Instance);
+            scopes.chain(instances).collect()
+        };
+        let garbage = Candidates::find(seeds).garbage();
+
+        // Each node is emptied outside its borrow, since dropping what it held can free
+        // other nodes
+        let mut emptied = 0;
+        for node in &garbage {
+            if node.empty() {
+                emptied += 1;
+            }
+        }
+        drop(garbage);
+
+        let mut state = self.state.borrow_mut();
+        state.prune();
+        let live = state.scopes.len() + state.instances.len();
+        state.tracked_since_collection = 0;
+        state.next_collection = (live * state.growth_factor).max(INITIAL_THRESHOLD);
+        emptied
+    }
+}
+
+impl State {
+    fn tracked(&mut self) {
+        self.tracked_since_collection += 1;
+        // A weak handle keeps its allocation alive, so handles to freed objects are
+        // dropped as they pile up rather than waiting for a collection
+        if self.scopes.len() + self.instances.len() >= self.next_prune {
+            self.prune();
+            self.next_prune = ((self.scopes.len() + self.instances.len()) * 2).max(INITIAL_THRESHOLD);
+        }
+    }
+
+    fn prune(&mut self) {
+        self.scopes.retain(|scope| scope.strong_count() > 0);
+        self.instances.retain(|instance| instance.strong_count() > 0);
+    }
+}
+
+// Anything shared that can refer to other values. Classes can't be emptied, but a cycle
+// through one always passes through a scope, which can.
+#[derive(Clone)]
+enum Node {
+    Scope(Enclosure),
+    Instance(Rc<RefCell<Instance>>),
+    Class(Rc<Class>),
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<ObjectMap>>),
+}
+
+impl Node {
+    fn address(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::as_ptr(scope) as usize,
+            Node::Instance(instance) => Rc::as_ptr(instance) as usize,
+            Node::Class(class) => Rc::as_ptr(class) as usize,
+            Node::List(list) => Rc::as_ptr(list) as usize,
+            Node::Map(map) => Rc::as_ptr(map) as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::strong_count(scope),
+            Node::Instance(instance) => Rc::strong_count(instance),
+            Node::Class(class) => Rc::strong_count(class),
+            Node::List(list) => Rc::strong_count(list),
+            Node::Map(map) => Rc::strong_count(map),
+        }
+    }
+
+    // Calls `visit` once for every strong reference the node holds to another node
+    fn references(&self, visit: &mut dyn FnMut(Node)) {
+        match self {
+            Node::Scope(scope) => scope.borrow().values().for_each(|value| object_references(value, visit)),
+            Node::Instance(instance) => {
+                let instance = instance.borrow();
+                visit(Node::Class(instance.class.clone()));
+                instance.fields().for_each(|value| object_references(value, visit));
+            }
+            Node::Class(class) => {
+                class.methods().for_each(|method| object_references(method, visit));
+                if let Some(superclass) = &class.superclass {
+                    visit(Node::Class(superclass.clone()));
+                }
+            }
+            Node::List(list) => list.borrow().iter().for_each(|value| object_references(value, visit)),
+            Node::Map(map) => map.borrow().values().for_each(|value| object_references(value, visit)),
+        }
+    }
+
+    // Returns whether there was anything to drop
+    fn empty(&self) -> bool {
+        match self {
+            Node::Scope(scope) => {
+                let values = std::mem::take(&mut *scope.borrow_mut());
+                !values.is_empty()
+            }
+            Node::Instance(instance) => {
+                let fields = instance.borrow_mut().take_fields();
+                !fields.is_empty()
+            }
+            Node::List(list) => {
+                let elements = std::mem::take(&mut *list.borrow_mut());
+                !elements.is_empty()
+            }
+            Node::Map(map) => {
+                let entries = std::mem::take(&mut *map.borrow_mut());
+                entries.len() > 0
+            }
+            Node::Class(_) => false,
+        }
+    }
+}
+
+fn object_references(object: &Object, visit: &mut dyn FnMut(Node)) {
+    match object {
+        Object::Function(_, _, closure) => closure.scopes().for_each(|scope| visit(Node::Scope(scope.clone()))),
+        Object::Class(class) => visit(Node::Class(class.clone())),
+        Object::Instance(instance) => visit(Node::Instance(instance.clone())),
+        Object::BoundMethod(instance, method, superclass) => {
+            visit(Node::Instance(instance.clone()));
+            object_references(method, visit);
+            if let Some(superclass) = superclass {
+                visit(Node::Class(superclass.clone()));
+            }
+        }
+        Object::List(list) => visit(Node::List(list.clone())),
+        Object::Map(map) => visit(Node::Map(map.clone())),
+        _ => {}
+    }
+}
+
+// Every node reachable from the seeds. The collector holds one strong reference to each.
+struct Candidates {
+    nodes: Vec<Node>,
+    positions: HashMap<usize, usize>,
+}
+
+impl Candidates {
+    fn find(seeds: Vec<Node>) -> Candidates {
+        let mut candidates = Candidates { nodes: Vec::new(), positions: HashMap::new() };
+        for seed in seeds {
+            candidates.add(seed);
+        }
+        let mut next = 0;
+        while next < candidates.nodes.len() {
+            let node = candidates.nodes[next].clone();
+            node.references(&mut |child| candidates.add(child));
+            next += 1;
+        }
+        candidates
+    }
+
+    fn add(&mut self, node: Node) {
+        let address = node.address();
+        if !self.positions.contains_key(&address) {
+            self.positions.insert(address, self.nodes.len());
+            self.nodes.push(node);
+        }
+    }
+
+    fn garbage(self) -> Vec<Node> {
+        // References from outside: the count less the collector's own and the candidates'
+        let mut outside: Vec<usize> = self.nodes.iter().map(|node| node.strong_count() - 1).collect();
+        for node in &self.nodes {
+            node.references(&mut |child| {
+                if let Some(position) = self.positions.get(&child.address()) {
+                    outside[*position] -= 1;
+                }
+            });
+        }
+
+        let mut live = vec![false; self.nodes.len()];
+        let mut gray: Vec<usize> = (0..self.nodes.len()).filter(|position| outside[*position] > 0).collect();
+        while let Some(position) = gray.pop() {
+            if live[position] {
+                continue;
+            }
+            live[position] = true;
+            self.nodes[position].references(&mut |child| gray.push(self.positions[&child.address()]));
+        }
+
+        self.nodes.into_iter().zip(live).filter(|(_, live)| !live).map(|(node, _)| node).collect()
+    }
+}
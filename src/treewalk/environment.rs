@@ -1,4 +1,9 @@
-use crate::treewalk::token::Object;
+use crate::common::token::Token;
+use crate::common::LoxError;
+use crate::common::output::Output;
+use crate::common::trace::Trace;
+use crate::treewalk::object::Object;
+use crate::treewalk::collector::Collector;
 use std::collections::HashMap;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -7,84 +12,137 @@ use std::fmt;
 
 pub type Enclosure = Rc<RefCell<HashMap<String, Object>>>;
 
+// Every environment descended from the same root shares its output, trace and collector
 #[derive(Clone)]
 pub struct Environment {
     values: Enclosure,
     pub enclosure_stack: Vec<Enclosure>,
+    pub output: Output,
+    pub trace: Trace,
+    pub collector: Collector,
 }
 
 impl Environment {
     pub fn new_root() -> Environment {
         let values = Rc::new(RefCell::new(HashMap::new()));
-        let mut enclosure_stack = Vec::new();
-        enclosure_stack.push(values.clone());
-        Environment { enclosure_stack, values }
+        let enclosure_stack = vec![values.clone()];
+        let output = Output::stdout();
+        let trace = Trace::default();
+        let collector = Collector::default();
+        Environment { enclosure_stack, values, output, trace, collector }
     }
 
     pub fn new_child(parent: &Environment) -> Environment {
         let values = Rc::new(RefCell::new(HashMap::new()));
         let mut enclosure_stack = parent.enclosure_stack.clone();
         enclosure_stack.push(values.clone());
-        Environment { enclosure_stack, values }
-    }
-
-    pub fn new_from_globals(parent: &Environment) -> Environment {
-        let values = Rc::new(RefCell::new(HashMap::new()));
-        let mut enclosure_stack = Vec::new();
-        enclosure_stack.push(parent.enclosure_stack[0].clone());
-        enclosure_stack.push(values.clone());
-        Environment { enclosure_stack, values }
+        let output = parent.output.clone();
+        let trace = parent.trace.clone();
+        let collector = parent.collector.clone();
+        collector.track_scope(&values);
+        Environment { enclosure_stack, values, output, trace, collector }
     }
 
     pub fn define(&self, name: String, value: Object) {
+        self.trace.event(|| format!("define {} = {:?}", name, value));
         let mut enclosure = self.values.borrow_mut();
         enclosure.insert(name, value);
     }
 
-    pub fn get(&self, name: String) -> Object {
-        println!("Get: {}", &name);
+    pub fn get(&self, name: &Token) -> Result<Object, LoxError> {
         for enclosure in self.enclosure_stack.iter().rev() {
-            let enclosure = enclosure.borrow();
-            println!("Get(Enclosure): {:?}", &enclosure);
-            println!();
-            if let Some(object) = enclosure.get(&name) {
-                if *object == Object::None {
-                    panic!("Variable is nil");
-                }
-                return object.clone();
+            if enclosure.borrow().contains_key(&name.lexeme) {
+                return lookup(enclosure, name);
             }
         }
-        eprintln!("Tried to access: {}", name);
-        panic!("Get: Undefined Variable!");
+        Err(undefined(name))
     }
 
-    pub fn assign(&self, name: String, object: Object) {
-        for enclosure in self.enclosure_stack.iter().rev() {
-            let mut enclosure = enclosure.borrow_mut();
-            if enclosure.contains_key(&name) {
-                enclosure.insert(name, object);
-                return;
-            }
+    // Reads a local that the resolver found `depth` enclosures above the current one
+    pub fn get_at(&self, depth: usize, name: &Token) -> Result<Object, LoxError> {
+        match self.ancestor(depth) {
+            Some(enclosure) => lookup(enclosure, name),
+            None => Err(undefined(name)),
+        }
+    }
+
+    pub fn get_global(&self, name: &Token) -> Result<Object, LoxError> {
+        lookup(&self.enclosure_stack[0], name)
+    }
+
+    pub fn global(&self, name: &str) -> Option<Object> {
+        self.enclosure_stack[0].borrow().get(name).cloned()
+    }
+
+    pub fn define_global(&self, name: String, value: Object) {
+        self.trace.event(|| format!("define {} = {:?}", name, value));
+        self.enclosure_stack[0].borrow_mut().insert(name, value);
+    }
+
+    // Global names and their values, sorted by name
+    pub fn globals(&self) -> Vec<(String, Object)> {
+        let mut globals: Vec<(String, Object)> = self.enclosure_stack[0].borrow().iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    pub fn assign_at(&self, depth: usize, name: &Token, object: Object) -> Result<(), LoxError> {
+        match self.ancestor(depth) {
+            Some(enclosure) => store(enclosure, name, object),
+            None => Err(undefined(name)),
         }
-        panic!("Assign: Undefined Variable!");
+    }
+
+    pub fn assign_global(&self, name: &Token, object: Object) -> Result<(), LoxError> {
+        store(&self.enclosure_stack[0], name, object)
+    }
+
+    // Every reference the environment holds to a scope. Its own scope comes up twice, since
+    // it is also the top of the stack.
+    pub fn scopes(&self) -> impl Iterator<Item = &Enclosure> {
+        std::iter::once(&self.values).chain(self.enclosure_stack.iter())
+    }
+
+    fn ancestor(&self, depth: usize) -> Option<&Enclosure> {
+        let index = self.enclosure_stack.len().checked_sub(depth + 1)?;
+        self.enclosure_stack.get(index)
     }
 }
 
-impl Drop for Environment {
-    fn drop(&mut self) {
-        println!("Going out of scope: {:#?}", &self.values);
+fn lookup(enclosure: &Enclosure, name: &Token) -> Result<Object, LoxError> {
+    match enclosure.borrow().get(&name.lexeme) {
+        Some(object) => Ok(object.clone()),
+        None => Err(undefined(name)),
     }
 }
 
+fn store(enclosure: &Enclosure, name: &Token, object: Object) -> Result<(), LoxError> {
+    match enclosure.borrow_mut().get_mut(&name.lexeme) {
+        Some(value) => {
+            *value = object;
+            Ok(())
+        }
+        None => Err(undefined(name)),
+    }
+}
+
+fn undefined(name: &Token) -> LoxError {
+    let message = format!("Undefined variable '{}'", name.lexeme);
+    LoxError::runtime(name, &message)
+}
+
 impl PartialOrd for Environment {
     fn partial_cmp(&self, _other: &Environment) -> Option<Ordering> {
         Some(Ordering::Less)
     }
 }
 
+// Closures are the same when they capture the same scope
 impl PartialEq for Environment {
-    fn eq(&self, _other: &Environment) -> bool {
-        false
+    fn eq(&self, other: &Environment) -> bool {
+        Rc::ptr_eq(&self.values, &other.values)
     }
 }
 
@@ -1,101 +1,355 @@
-use crate::treewalk::token::{Token, Object};
-use crate::treewalk::expression::Expr;
-use crate::treewalk::statement::Stmt;
-use crate::treewalk::token::token_type::TokenType;
+use crate::common::token::{Token, Span};
+use crate::common::expression::Expr;
+use crate::common::statement::Stmt;
+use crate::common::token::token_type::TokenType;
+use crate::common::list;
+use crate::common::map::Map;
+use crate::common::LoxError;
+use crate::common::scanner::Scanner;
+use crate::common::{parser, resolver};
+use crate::treewalk::object::Object;
 use crate::treewalk::environment::Environment;
+use crate::treewalk::class::{Class, Instance};
+use crate::treewalk::native::{self, Native};
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::io::Write;
+use std::ops::Range;
+use std::rc::Rc;
+
+// Owns the global environment, so globals, functions and classes defined by one call to
+// interpret are still there for the next. The REPL keeps one alive for the whole session,
+// and host applications use one to run Lox without going through files or stdin.
+pub struct Interpreter {
+    environment: Environment,
+}
 
-pub fn interpret(statements: Vec<Stmt>, is_repl: bool) -> Result<(), &'static str> {
-    let mut environment = Environment::new_root();
-    for statement in statements {
-        if let Err(why) = execute(statement, &mut environment, is_repl) {
-            return Err(why);
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut interpreter = Interpreter { environment: Environment::new_root() };
+        interpreter.define_native("clock", 0, native::clock);
+        interpreter.define_native("len", 1, native::len);
+        interpreter.define_native("push", 2, native::push);
+        interpreter.define_native("pop", 1, native::pop);
+        interpreter.define_native("insert", 3, native::insert);
+        interpreter.define_native("remove", 2, native::remove);
+        interpreter.define_native("keys", 1, native::keys);
+        interpreter.define_native("values", 1, native::values);
+        interpreter.define_native("has", 2, native::has);
+        interpreter.define_native("delete", 2, native::delete);
+        interpreter.define_native("range", 2, native::range);
+        interpreter
+    }
+
+    // Makes a Rust function callable from Lox as a global. Calls with the wrong number of
+    // arguments fail before the function runs.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+        where F: Fn(&[Object]) -> Result<Object, String> + 'static
+    {
+        let native = Native::new(name, arity, Box::new(function));
+        self.environment.define_global(name.to_string(), Object::Native(Rc::new(native)));
+    }
+
+    // Breaks unreachable cycles once enough scopes and instances have been created since
+    // the last collection
+    pub fn interpret(&mut self, statements: Vec<Stmt>, is_repl: bool) -> Result<(), LoxError> {
+        let result = interpret(statements, &mut self.environment, is_repl);
+        if self.environment.collector.should_collect() {
+            self.collect_garbage();
+        }
+        result
+    }
+
+    // Frees cycles of closures and instances, including those through lists and maps, that
+    // only refer to each other. Anything the globals or the host still hold is kept. Returns
+    // the number of objects emptied.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.environment.collector.collect()
+    }
+
+    pub fn set_gc_growth_factor(&mut self, growth_factor: usize) {
+        self.environment.collector.set_growth_factor(growth_factor);
+    }
+
+    // Runs the source and returns the value of its final statement when that is an
+    // expression, or nil otherwise
+    pub fn eval(&mut self, source: &str) -> Result<Object, LoxError> {
+        let mut tokens = Scanner::new(source).scan_tokens()?;
+        let mut statements = parser::parse(&mut tokens).map_err(|mut errors| errors.remove(0))?;
+        resolver::resolve(&mut statements).map_err(|mut errors| errors.remove(0))?;
+
+        let last = match statements.last() {
+            Some(Stmt::Expr(_)) => statements.pop(),
+            _ => None,
+        };
+        interpret(statements, &mut self.environment, false)?;
+        match last {
+            Some(Stmt::Expr(expr)) => evaluate(*expr, &mut self.environment),
+            _ => Ok(Object::Nil),
+        }
+    }
+
+    // Calls a global function or class with arguments supplied by the host
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Object>) -> Result<Object, LoxError> {
+        let token = Token::new_identifier(name, 0).at(Span::host());
+        let callee = self.environment.get_global(&token)?;
+        let constructs = matches!(callee, Object::Class(_));
+        let result = call(callee, arguments, &token)?;
+        track_instance(&self.environment, constructs, &result);
+        Ok(result)
+    }
+
+    // Forgets every global except natives
+    pub fn reset(&mut self) {
+        let mut environment = Environment::new_root();
+        environment.output = self.environment.output.clone();
+        environment.trace = self.environment.trace.clone();
+        environment.collector = self.environment.collector.clone();
+        for (name, value) in self.environment.globals() {
+            if let Object::Native(_) = value {
+                environment.define_global(name, value);
+            }
         }
+        self.environment = environment;
+        self.collect_garbage();
+    }
+
+    // Writes a line to the writer for every statement, variable access, scope and call.
+    // Does nothing unless built with the `trace` feature.
+    pub fn set_trace(&mut self, writer: Box<dyn Write>) {
+        self.environment.trace.enable(writer);
+    }
+
+    // Sends the output of print statements to the writer instead of stdout
+    pub fn set_output(&mut self, writer: Box<dyn Write>) {
+        self.environment.output.redirect(writer);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.environment.global(name)
+    }
+
+    // Defines the global if it doesn't exist yet
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.environment.define_global(name.to_string(), value);
+    }
+
+    pub(crate) fn environment(&self) -> &Environment {
+        &self.environment
+    }
+}
+
+// How a statement finished. Break, Continue and Return unwind through the enclosing blocks
+// until they reach the loop or function they belong to.
+enum ControlFlow {
+    Normal,
+    Break,
+    Continue,
+    Return(Object),
+}
+
+pub fn interpret(statements: Vec<Stmt>, env: &mut Environment, is_repl: bool) -> Result<(), LoxError> {
+    for statement in statements {
+        execute(statement, env, is_repl)?;
     }
     Ok(())
 }
 
-fn execute(statement: Stmt, env: &mut Environment, is_repl: bool) -> Result<Object, &'static str> {
+fn execute(statement: Stmt, env: &mut Environment, is_repl: bool) -> Result<ControlFlow, LoxError> {
+    env.trace.event(|| format!("statement {}", describe(&statement)));
     let expr = match &statement {
         Stmt::Expr(expr) => *expr.clone(),
-        Stmt::Print(expr) => *expr.clone(),
+        Stmt::Print(_, expr) => *expr.clone(),
         Stmt::Var(_, expr) => *expr.clone(),
         Stmt::Block(_) => Expr::Empty,
         Stmt::If(expr, _, _) => *expr.clone(),
-        Stmt::While(expr, _) => *expr.clone(),
+        Stmt::While(_, _, _) => Expr::Empty,
+        Stmt::ForIn(_, _, _) => Expr::Empty,
         Stmt::Function(_, _, _) => Expr::Empty,
-        Stmt::Break => Expr::Empty,
+        Stmt::Class(_, _, _) => Expr::Empty,
+        Stmt::Break(_) | Stmt::Continue(_) => Expr::Empty,
         Stmt::Return(_, expr) => *expr.clone(),
     };
     let object = evaluate(expr, env)?;
 
     match statement {
-        Stmt::Expr(_) if is_repl => println!("{:?}", object),
+        Stmt::Expr(_) if is_repl => print(env, &object).map_err(|message| LoxError::Runtime(Span::host(), message))?,
         Stmt::Expr(_) => {},
-        Stmt::Print(_) => println!("{:?}", object),
+        Stmt::Print(keyword, _) => print(env, &object).map_err(|message| LoxError::runtime(&keyword, &message))?,
         Stmt::Var(token, _) => env.define(token.lexeme, object),
         Stmt::Block(block) => {
             return execute_block(block, env);
         },
         Stmt::If(_, then_branch, else_branch) => {
-            let object = match object {
-                Object::Bool(true) => execute(*then_branch, env, false)?,
-                Object::Bool(false) => execute(*else_branch, env, false)?,
-                _ => panic!("Conditional did not evaluate to true or false"),
-            };
-            return Ok(object);
+            if truthiness(&object) {
+                return execute(*then_branch, env, false);
+            }
+            return execute(*else_branch, env, false);
         },
-        Stmt::While(expr, body) => {
+        Stmt::While(expr, body, increment) => {
             while truthiness(&evaluate(*expr.clone(), env)?) {
-                let object = execute(*body.clone(), env, false)?;
-                if should_exit(&*body) {
-                    break;
-                }
-                if object != Object::Nil {
-                    return Ok(object);
+                match execute(*body.clone(), env, false)? {
+                    ControlFlow::Normal | ControlFlow::Continue => {}
+                    ControlFlow::Break => break,
+                    flow @ ControlFlow::Return(_) => return Ok(flow),
                 }
+                evaluate(*increment.clone(), env)?;
             }
         },
+        Stmt::ForIn(name, iterable, body) => return for_in(name, *iterable, *body, env),
         Stmt::Function(name, parameters, body) => {
-            let closure = Environment::new_child(env);
-            println!("Function: {:?}", name);
-            println!("Parent: {:?}", &env);
-            let func_object = Object::Function(parameters, body, closure);
+            let closure = env.clone();
+            let func_object = Object::Function(parameters, Rc::new(body), closure);
             env.define(name.lexeme, func_object);
         }
-        Stmt::Break => {},
-        Stmt::Return(_, _) => {
-            return Ok(object)
-        },
+        Stmt::Class(name, superclass, methods) => {
+            let superclass = match superclass {
+                Some(superclass) => Some(evaluate_superclass(&name, *superclass, env)?),
+                None => None,
+            };
+
+            let mut class_methods = HashMap::new();
+            for method in methods {
+                if let Stmt::Function(method_name, parameters, body) = method {
+                    let closure = env.clone();
+                    let method_object = Object::Function(parameters, Rc::new(body), closure);
+                    class_methods.insert(method_name.lexeme, method_object);
+                }
+            }
+            let class = Class::new(&name.lexeme, superclass, class_methods);
+            env.define(name.lexeme, Object::Class(Rc::new(class)));
+        }
+        Stmt::Break(_) => return Ok(ControlFlow::Break),
+        Stmt::Continue(_) => return Ok(ControlFlow::Continue),
+        Stmt::Return(_, _) => return Ok(ControlFlow::Return(object)),
+    }
+    Ok(ControlFlow::Normal)
+}
+
+// Each pass through the body gets a new scope holding the loop variable, so closures made
+// in the body capture that pass's value
+fn for_in(name: Token, iterable: Expr, body: Stmt, env: &mut Environment) -> Result<ControlFlow, LoxError> {
+    let mut iteration = Iteration::new(evaluate(iterable, env)?, &name)?;
+    while let Some(value) = iteration.next(&name)? {
+        let mut scope = Environment::new_child(env);
+        scope.define(name.lexeme.clone(), value);
+        match execute(body.clone(), &mut scope, false)? {
+            ControlFlow::Normal | ControlFlow::Continue => {}
+            ControlFlow::Break => break,
+            flow @ ControlFlow::Return(_) => return Ok(flow),
+        }
+    }
+    Ok(ControlFlow::Normal)
+}
+
+// What a for-in loop steps through. Lists are read as the loop goes, so elements pushed
+// by the body are visited too. A map's keys and a string's characters are gathered up
+// front. Instances supply an iterator through the protocol: `iterator()` returns an object
+// whose `hasNext()` and `next()` are called before each pass.
+enum Iteration {
+    Elements(Rc<RefCell<Vec<Object>>>, usize),
+    Protocol(Object),
+}
+
+impl Iteration {
+    fn new(iterable: Object, name: &Token) -> Result<Iteration, LoxError> {
+        let snapshot = |values: Vec<Object>| Iteration::Elements(Rc::new(RefCell::new(values)), 0);
+        match iterable {
+            Object::List(list) => Ok(Iteration::Elements(list, 0)),
+            Object::Map(map) => Ok(snapshot(map.borrow().keys().map(Object::from).collect())),
+            Object::String(string) => Ok(snapshot(string.chars().map(|c| Object::String(c.to_string())).collect())),
+            Object::Instance(_) => Ok(Iteration::Protocol(invoke(&iterable, "iterator", name)?)),
+            _ => Err(LoxError::runtime(name, "Can only iterate over lists, maps, strings and iterators")),
+        }
+    }
+
+    fn next(&mut self, name: &Token) -> Result<Option<Object>, LoxError> {
+        match self {
+            Iteration::Elements(elements, position) => {
+                let value = elements.borrow().get(*position).cloned();
+                *position += 1;
+                Ok(value)
+            }
+            Iteration::Protocol(iterator) => {
+                if !truthiness(&invoke(iterator, "hasNext", name)?) {
+                    return Ok(None);
+                }
+                invoke(iterator, "next", name).map(Some)
+            }
+        }
     }
-    Ok((Object::Nil))
 }
 
-fn execute_block(statements: Vec<Box<Stmt>>, parent_env: &Environment) -> Result<Object, &'static str> {
+// Calls a method, or a callable field, with no arguments
+fn invoke(object: &Object, method: &str, token: &Token) -> Result<Object, LoxError> {
+    let method = Token::new_identifier(method, token.line()).at(token.span());
+    match object {
+        Object::Instance(instance) => call(get_property(instance, &method)?, Vec::new(), token),
+        _ => Err(LoxError::runtime(token, "Only instances have properties")),
+    }
+}
+
+fn print(env: &Environment, object: &Object) -> Result<(), String> {
+    env.output.print_line(&format!("{:?}", object))
+}
+
+fn execute_block(statements: Vec<Stmt>, parent_env: &Environment) -> Result<ControlFlow, LoxError> {
     let mut env = Environment::new_child(parent_env);
+    let depth = env.enclosure_stack.len() - 1;
+    env.trace.event(|| format!("push scope {}", depth));
+    let result = execute_statements(statements, &mut env);
+    env.trace.event(|| format!("pop scope {}", depth));
+    result
+}
+
+// A one-line summary for traces; the Debug form of a statement includes its whole body
+fn describe(statement: &Stmt) -> String {
+    match statement {
+        Stmt::Block(_) => "block".to_string(),
+        Stmt::Expr(expr) => format!("expression {:?}", expr),
+        Stmt::Print(_, expr) => format!("print {:?}", expr),
+        Stmt::Return(_, expr) => format!("return {:?}", expr),
+        Stmt::Var(name, _) => format!("var {}", name.lexeme),
+        Stmt::If(condition, _, _) => format!("if {:?}", condition),
+        Stmt::While(condition, _, _) => format!("while {:?}", condition),
+        Stmt::ForIn(name, iterable, _) => format!("for {} in {:?}", name.lexeme, iterable),
+        Stmt::Function(name, _, _) => format!("fun {}", name.lexeme),
+        Stmt::Class(name, _, _) => format!("class {}", name.lexeme),
+        Stmt::Break(_) => "break".to_string(),
+        Stmt::Continue(_) => "continue".to_string(),
+    }
+}
+
+// Runs statements in the given environment, without introducing a new scope. Stops at the
+// first statement that breaks or returns.
+fn execute_statements(statements: Vec<Stmt>, env: &mut Environment) -> Result<ControlFlow, LoxError> {
     for statement in statements {
-        if let Stmt::Break = *statement {
-            break;
-        }
-        let result = execute(*statement, &mut env, false)?;
-        if result != Object::Nil {
-            println!("Returning a result!");
-            return Ok(result);
+        match execute(statement, env, false)? {
+            ControlFlow::Normal => {}
+            flow => return Ok(flow),
         }
     }
-    Ok(Object::Nil)
+    Ok(ControlFlow::Normal)
 }
 
-fn evaluate(expression: Expr, env: &mut Environment) -> Result<Object, &'static str> {
+fn evaluate(expression: Expr, env: &mut Environment) -> Result<Object, LoxError> {
     match expression {
-        Expr::Literal(object) => Ok(object),
+        Expr::Literal(literal) => Ok(Object::from(literal)),
         Expr::Grouping(expr) => evaluate(*expr, env),
         Expr::Unary(token, expr) => {
             let right = evaluate(*expr, env)?;
-            match token.type_of {
+            let result = match token.type_of {
                 TokenType::Bang => unary_bang(right),
                 TokenType::Minus => unary_minus(right),
-                _ => return Err("Could not match unary operator"),
-            }
+                _ => Err("Could not match unary operator"),
+            };
+            result.map_err(|message| LoxError::runtime(&token, message))
         }
         Expr::Logical(left, token, right) => {
             let left = evaluate(*left, env)?;
@@ -107,7 +361,7 @@ fn evaluate(expression: Expr, env: &mut Environment) -> Result<Object, &'static
                 TokenType::Or if !truth => evaluate(*right, env)?,
                 TokenType::And if !truth => left,
                 TokenType::And if truth => evaluate(*right, env)?,
-                _ => return Err("Conditional did not evaluate to true or false"),
+                _ => return Err(LoxError::runtime(&token, "Conditional did not evaluate to true or false")),
             };
             Ok(object)
         }
@@ -115,7 +369,7 @@ fn evaluate(expression: Expr, env: &mut Environment) -> Result<Object, &'static
         Expr::Binary(left, token, right) => {
             let left = evaluate(*left, env)?;
             let right = evaluate(*right, env)?;
-            match token.type_of {
+            let result = match token.type_of {
                 TokenType::Minus => binary_arithmetic(Some(minus), None, left, right),
                 TokenType::Slash => binary_arithmetic(Some(slash), None, left, right),
                 TokenType::Star => binary_arithmetic(Some(star), None, left, right),
@@ -126,46 +380,272 @@ fn evaluate(expression: Expr, env: &mut Environment) -> Result<Object, &'static
                 TokenType::LessEqual => binary_compare(less_equal, shorter_equal, left, right),
                 TokenType::EqualEqual => binary_equal_equal(left, right),
                 TokenType::BangEqual => binary_bang_equal(left, right),
-                _ => return Err("Could not match binary operator"),
-            }
+                _ => Err("Could not match binary operator"),
+            };
+            result.map_err(|message| LoxError::runtime(&token, message))
         }
-        Expr::Variable(token) => Ok(env.get(token.lexeme)),
-        Expr::Assign(token, expr) => {
+        Expr::Variable(token, depth) => {
+            let object = match depth {
+                Some(depth) => env.get_at(depth, &token)?,
+                None => env.get_global(&token)?,
+            };
+            env.trace.event(|| format!("read {} = {:?}", token.lexeme, object));
+            Ok(object)
+        }
+        Expr::Assign(token, expr, depth) => {
             let object = evaluate(*expr, env)?;
-            env.assign(token.lexeme, object);
-            Ok(Object::None)
+            env.trace.event(|| format!("write {} = {:?}", token.lexeme, object));
+            match depth {
+                Some(depth) => env.assign_at(depth, &token, object.clone())?,
+                None => env.assign_global(&token, object.clone())?,
+            }
+            Ok(object)
+        }
+        Expr::Call(callee_expr, paren, arguments) => {
+            let callee = evaluate(*callee_expr.clone(), env)?;
+            let mut evaluated = Vec::new();
+            for argument in arguments {
+                evaluated.push(evaluate(argument, env)?);
+            }
+
+            env.trace.event(|| format!("call {:?} with {:?}", callee_expr, evaluated));
+            let constructs = matches!(callee, Object::Class(_));
+            let result = call(callee, evaluated, &paren)?;
+            track_instance(env, constructs, &result);
+            env.trace.event(|| format!("return from {:?} with {:?}", callee_expr, result));
+            Ok(result)
+        }
+        Expr::Get(object, name) => {
+            match evaluate(*object, env)? {
+                Object::Instance(instance) => get_property(&instance, &name),
+                _ => Err(LoxError::runtime(&name, "Only instances have properties")),
+            }
         }
-        Expr::Call(callee, _, arguments) => {
-            let callee = evaluate(*callee, env)?;
-            if let Object::Function(parameters, function_block, closure) = callee {
-                let arguments: Vec<Object> = arguments.iter()
-                    .map(|argument| evaluate(*argument.clone(), env))
-                    .map(|result| result.unwrap())
-                    .collect();
-                if parameters.len() != arguments.len() {
-                    return Err("Arguments do not match Parameter arity");
+        Expr::Set(object, name, value) => {
+            match evaluate(*object, env)? {
+                Object::Instance(instance) => {
+                    let value = evaluate(*value, env)?;
+                    instance.borrow_mut().set_field(name.lexeme, value.clone());
+                    Ok(value)
                 }
+                _ => Err(LoxError::runtime(&name, "Only instances have fields")),
+            }
+        }
+        Expr::List(_, elements) => list_literal(elements, env),
+        Expr::Map(brace, entries) => map_literal(&brace, entries, env),
+        Expr::Index(object, bracket, index) => get_index(*object, &bracket, *index, env),
+        Expr::SetIndex(object, bracket, index, value) => set_index(*object, &bracket, *index, *value, env),
+        Expr::Slice(object, bracket, start, end) => slice(*object, &bracket, *start, *end, env),
+        Expr::This(keyword, depth) => env.get_at(depth, &Token::new_identifier("this", keyword.line()).at(keyword.span())),
+        // The resolver only lets super into methods of subclasses, where it sits beside this
+        Expr::Super(keyword, method, depth) => {
+            let superclass = env.get_at(depth, &Token::new_identifier("super", keyword.line()).at(keyword.span()))?;
+            let this = env.get_at(depth, &Token::new_identifier("this", keyword.line()).at(keyword.span()))?;
+            match (superclass, this) {
+                (Object::Class(superclass), Object::Instance(instance)) => {
+                    match superclass.find_method(&method.lexeme) {
+                        Some((found, superclass)) => {
+                            Ok(Object::BoundMethod(instance, Box::new(found), superclass))
+                        }
+                        None => {
+                            let message = format!("Undefined property '{}'", method.lexeme);
+                            Err(LoxError::runtime(&method, &message))
+                        }
+                    }
+                }
+                _ => Err(LoxError::runtime(&keyword, "Can't use 'super' in a class with no superclass")),
+            }
+        }
+        Expr::Lambda(_, parameters, body) => Ok(Object::Function(parameters, Rc::new(body), env.clone())),
+        Expr::Empty => Ok(Object::Nil),
+    }
+}
+
+// Instances are only created by calling a class
+fn track_instance(env: &Environment, constructs: bool, result: &Object) {
+    if let (true, Object::Instance(instance)) = (constructs, result) {
+        env.collector.track_instance(instance);
+    }
+}
 
-                let mut function_env = bind_parameters(parameters, arguments, &closure);
-                let function_block = Stmt::Block(function_block);
-                let result = execute(function_block, &mut function_env, false)?;
-                return Ok(result);
+fn call(callee: Object, arguments: Vec<Object>, paren: &Token) -> Result<Object, LoxError> {
+    match callee {
+        Object::Function(parameters, body, closure) => {
+            call_function(parameters, body, &closure, None, None, arguments, paren)
+        }
+        Object::Native(native) => {
+            if native.arity != arguments.len() {
+                return Err(LoxError::runtime(paren, "Arguments do not match Parameter arity"));
+            }
+            native.call(&arguments).map_err(|message| LoxError::runtime(paren, &message))
+        }
+        // A bound init returns its instance however it's called, like the class does
+        Object::BoundMethod(instance, method, superclass) => {
+            let initializer = instance.borrow().class.is_initializer(&method);
+            if let Object::Function(parameters, body, closure) = *method {
+                let this = Object::Instance(instance.clone());
+                let superclass = superclass.map(Object::Class);
+                let result = call_function(parameters, body, &closure, Some(this), superclass, arguments, paren)?;
+                Ok(if initializer { Object::Instance(instance) } else { result })
             } else {
-                panic!("Couldn't map callee to function");
+                Err(LoxError::runtime(paren, "Bound method is not a function"))
             }
         }
-        Expr::Empty => Ok(Object::Nil),
+        Object::Class(class) => {
+            let instance = Rc::new(RefCell::new(Instance::new(class.clone())));
+            match class.find_method("init") {
+                Some((initializer, superclass)) => {
+                    let initializer = Object::BoundMethod(instance.clone(), Box::new(initializer), superclass);
+                    call(initializer, arguments, paren)?;
+                }
+                None if !arguments.is_empty() => {
+                    return Err(LoxError::runtime(paren, "Arguments do not match Parameter arity"));
+                }
+                None => {}
+            }
+            Ok(Object::Instance(instance))
+        }
+        _ => Err(LoxError::runtime(paren, "Can only call functions and classes")),
     }
 }
 
-fn unary_bang(right: Object) -> Result<Object, &'static str> {
-    match right {
-        Object::Nil => Ok(Object::Bool(false)),
-        Object::Bool(value) => Ok(Object::Bool(!value)),
-        _ => Ok(Object::Bool(true)),
+fn call_function(parameters: Vec<Token>, body: Rc<Vec<Stmt>>, closure: &Environment, this: Option<Object>, superclass: Option<Object>, arguments: Vec<Object>, paren: &Token) -> Result<Object, LoxError> {
+    if parameters.len() != arguments.len() {
+        return Err(LoxError::runtime(paren, "Arguments do not match Parameter arity"));
+    }
+
+    let mut function_env = bind_parameters(parameters, arguments, closure);
+    if let Some(this) = this {
+        function_env.define("this".to_string(), this);
+    }
+    if let Some(superclass) = superclass {
+        function_env.define("super".to_string(), superclass);
+    }
+    match execute_statements(body.to_vec(), &mut function_env)? {
+        ControlFlow::Return(object) => Ok(object),
+        _ => Ok(Object::Nil),
     }
 }
 
+// Fields shadow methods; methods are bound to the instance they were accessed from
+fn get_property(instance: &Rc<RefCell<Instance>>, name: &Token) -> Result<Object, LoxError> {
+    if let Some(field) = instance.borrow().get_field(&name.lexeme) {
+        return Ok(field);
+    }
+
+    let method = instance.borrow().class.find_method(&name.lexeme);
+    match method {
+        Some((method, superclass)) => {
+            Ok(Object::BoundMethod(instance.clone(), Box::new(method), superclass))
+        }
+        None => {
+            let message = format!("Undefined property '{}'", name.lexeme);
+            Err(LoxError::runtime(name, &message))
+        }
+    }
+}
+
+fn evaluate_superclass(name: &Token, superclass: Expr, env: &mut Environment) -> Result<Rc<Class>, LoxError> {
+    if let Expr::Variable(superclass_name, _) = &superclass {
+        if superclass_name.lexeme == name.lexeme {
+            return Err(LoxError::runtime(superclass_name, "A class can't inherit from itself"));
+        }
+    }
+
+    match evaluate(superclass, env)? {
+        Object::Class(superclass) => Ok(superclass),
+        _ => Err(LoxError::runtime(name, "Superclass must be a class")),
+    }
+}
+
+const INDEX_ERROR: &str = "Only lists and maps can be indexed";
+
+// Lists and maps are kept out of evaluate, whose stack frame every nested call pays for
+fn list_literal(elements: Vec<Expr>, env: &mut Environment) -> Result<Object, LoxError> {
+    let mut values = Vec::new();
+    for element in elements {
+        values.push(evaluate(element, env)?);
+    }
+    Ok(Object::List(Rc::new(RefCell::new(values))))
+}
+
+fn map_literal(brace: &Token, entries: Vec<(Expr, Expr)>, env: &mut Environment) -> Result<Object, LoxError> {
+    let mut map = Map::new();
+    for (key, value) in entries {
+        let key = evaluate(key, env)?.to_key()
+            .map_err(|message| LoxError::runtime(brace, &message))?;
+        map.insert(key, evaluate(value, env)?);
+    }
+    Ok(Object::Map(Rc::new(RefCell::new(map))))
+}
+
+fn get_index(object: Expr, bracket: &Token, index: Expr, env: &mut Environment) -> Result<Object, LoxError> {
+    let object = evaluate(object, env)?;
+    let index = evaluate(index, env)?;
+    let value = match object {
+        Object::List(list) => {
+            let elements = list.borrow();
+            element_index(&index, elements.len()).map(|position| elements[position].clone())
+        }
+        Object::Map(map) => index.to_key().and_then(|key| {
+            map.borrow().get(&key).cloned().ok_or_else(|| format!("Undefined key {:?}", index))
+        }),
+        _ => Err(INDEX_ERROR.to_string()),
+    };
+    value.map_err(|message| LoxError::runtime(bracket, &message))
+}
+
+// Assigning to a key a map doesn't have adds it
+fn set_index(object: Expr, bracket: &Token, index: Expr, value: Expr, env: &mut Environment) -> Result<Object, LoxError> {
+    let object = evaluate(object, env)?;
+    let index = evaluate(index, env)?;
+    let value = evaluate(value, env)?;
+    let stored = match object {
+        Object::List(list) => {
+            let length = list.borrow().len();
+            element_index(&index, length).map(|position| list.borrow_mut()[position] = value.clone())
+        }
+        Object::Map(map) => index.to_key().map(|key| map.borrow_mut().insert(key, value.clone())),
+        _ => Err(INDEX_ERROR.to_string()),
+    };
+    stored.map_err(|message| LoxError::runtime(bracket, &message))?;
+    Ok(value)
+}
+
+fn slice(object: Expr, bracket: &Token, start: Expr, end: Expr, env: &mut Environment) -> Result<Object, LoxError> {
+    let list = match evaluate(object, env)? {
+        Object::List(list) => list,
+        _ => return Err(LoxError::runtime(bracket, "Only lists can be sliced")),
+    };
+    let start = evaluate(start, env)?;
+    let end = evaluate(end, env)?;
+    let elements = list.borrow();
+    let range = slice_range(start, end, elements.len())
+        .map_err(|message| LoxError::runtime(bracket, &message))?;
+    Ok(Object::List(Rc::new(RefCell::new(elements[range].to_vec()))))
+}
+
+fn element_index(index: &Object, length: usize) -> Result<usize, String> {
+    match index {
+        Object::Number(index) => list::element(*index, length),
+        _ => Err("Index must be a number".to_string()),
+    }
+}
+
+// A bound left out of a slice evaluates to nil
+fn slice_range(start: Object, end: Object, length: usize) -> Result<Range<usize>, String> {
+    let bound = |bound| match bound {
+        Object::Nil => Ok(None),
+        Object::Number(bound) => Ok(Some(bound)),
+        _ => Err("Slice bounds must be numbers".to_string()),
+    };
+    list::slice(bound(start)?, bound(end)?, length)
+}
+
+fn unary_bang(right: Object) -> Result<Object, &'static str> {
+    Ok(Object::Bool(!truthiness(&right)))
+}
+
 fn unary_minus(right: Object) -> Result<Object, &'static str> {
     match right {
         Object::Number(number) => Ok(Object::Number(-number)),
@@ -215,44 +695,43 @@ fn less_equal(l: f64, r: f64) -> bool { l <= r }
 
 fn shorter_equal(l: &str, r: &str) -> bool { l <= r }
 
+// Values of different types are never equal. Functions, classes, instances, lists and maps
+// are only equal to themselves; a bound method is equal to the same method bound to the
+// same instance.
 fn binary_equal_equal(left: Object, right: Object) -> Result<Object, &'static str> {
-    match (left, right) {
-        (Object::Number(left), Object::Number(right)) => Ok(Object::Bool(left == right)),
-        (Object::String(left), Object::String(right)) => Ok(Object::Bool(left == right)),
-        (Object::Bool(left), Object::Bool(right)) => Ok(Object::Bool(left == right)),
-        (Object::Nil, Object::Nil) => Ok(Object::Bool(true)),
-        (Object::None, Object::None) => Ok(Object::Bool(true)),
-        _ => Err("Cannot perform ==/2 on a non-number or non-string"),
-    }
+    let equal = match (&left, &right) {
+        (Object::Nil, Object::Nil) => true,
+        (Object::Function(_, left, _), Object::Function(_, right, _)) => Rc::ptr_eq(left, right),
+        (Object::BoundMethod(left, left_method, _), Object::BoundMethod(right, right_method, _)) => {
+            let same_method = match (&**left_method, &**right_method) {
+                (Object::Function(_, left, _), Object::Function(_, right, _)) => Rc::ptr_eq(left, right),
+                _ => false,
+            };
+            Rc::ptr_eq(left, right) && same_method
+        }
+        (Object::List(left), Object::List(right)) => Rc::ptr_eq(left, right),
+        (Object::Map(left), Object::Map(right)) => Rc::ptr_eq(left, right),
+        _ => left == right,
+    };
+    Ok(Object::Bool(equal))
 }
 
 fn binary_bang_equal(left: Object, right: Object) -> Result<Object, &'static str> {
     match binary_equal_equal(left, right)? {
         Object::Bool(result) => Ok(Object::Bool(!result)),
-        _ => Ok(Object::None),
+        other => Ok(other),
     }
 }
 
+// `nil` and `false` are falsey, everything else is truthy
 fn truthiness(object: &Object) -> bool {
-    match object {
-        Object::Bool(truthiness) => *truthiness,
-        _ => panic!("Object was not true or false"),
-    }
-}
-
-fn should_exit(statement: &Stmt) -> bool {
-    if let Stmt::Block(statements) = statement {
-        for statement in statements {
-            if **statement == Stmt::Break {
-                return true;
-            }
-        }
-    }
-    return false;
+    !matches!(object, Object::Nil | Object::Bool(false))
 }
 
-fn bind_parameters(parameters: Vec<Token>, arguments: Vec<Object>, env: &Environment) -> Environment {
-    let env = Environment::new_from_globals(env);
+// The call's scope sits directly on top of the closure, so the function keeps sharing
+// (and mutating) the enclosures that were live where it was declared
+fn bind_parameters(parameters: Vec<Token>, arguments: Vec<Object>, closure: &Environment) -> Environment {
+    let env = Environment::new_child(closure);
 
     for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
         env.define(parameter.lexeme.clone(), argument.clone());
@@ -1,131 +1,980 @@
-mod token;
-mod scanner;
-mod expression;
-mod statement;
-mod parser;
+mod object;
 mod interpreter;
 mod environment;
+mod collector;
+mod class;
+mod native;
 
-use std::io;
-use std::io::Write;
-use std::fs;
-
+pub use self::interpreter::Interpreter;
+pub use self::object::Object as Value;
 
-pub fn run_prompt() {
-    loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-        io::stdin().read_line(&mut line).expect("Couldn't Read");
+use crate::common::{parser, resolver, LoxError};
+use crate::common::scanner::Scanner;
+use crate::repl::{self, Session};
+use std::fs;
 
-        if let Err(why) = run(&line, true) {
-            report(0, &line, why);
-        }
 
-        io::stdout().flush().unwrap();
-    }
+// One interpreter serves every line, so definitions carry over between them
+pub fn run_prompt(mut interpreter: Interpreter) {
+    repl::run_prompt(&mut interpreter);
 }
 
-pub fn run_file(path: &str) {
-    let mut line = String::new();
+pub fn run_file(path: &str, mut interpreter: Interpreter) {
     let contents = fs::read_to_string(path)
         .expect("Couldn't open file!");
 
-    if let Err(why) = run(&contents, false) {
-        report(0, &line, why);
+    if let Err(errors) = run(&mut interpreter, &contents, false) {
+        repl::report(&errors);
     }
 }
 
-fn run(source: &str, is_repl: bool) -> Result<(), &'static str> {
-    let scanner = crate::treewalk::scanner::Scanner::new(source.trim());
-    let mut tokens = scanner.scan_tokens();
-    println!("Scanned Tokens: {:?}", tokens.clone());
+fn run(interpreter: &mut Interpreter, source: &str, is_repl: bool) -> Result<(), Vec<LoxError>> {
+    let scanner = Scanner::new(source);
+    let mut tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
+
+    let mut expressions = parser::parse(&mut tokens)?;
 
-    let expressions = parser::parse(&mut tokens);
-    println!("Parsed Expression: {:?}", expressions.clone());
+    resolver::resolve(&mut expressions)?;
 
-    crate::treewalk::interpreter::interpret(expressions, is_repl)
+    interpreter.interpret(expressions, is_repl)
+        .map_err(|error| vec![error])
 }
 
-// TODO: Make into a macro?
-fn report(line: u32, source: &str, message: &str) {
-    eprintln!("Line: {} Error: {} : {}", line, source.trim(), message);
+impl Session for Interpreter {
+    fn run(&mut self, source: &str, is_repl: bool) -> Result<(), Vec<LoxError>> {
+        run(self, source, is_repl)
+    }
+
+    fn bindings(&self) -> Vec<(String, String)> {
+        self.environment().globals().into_iter()
+            .map(|(name, value)| (name, format!("{:?}", value)))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        Interpreter::reset(self);
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::treewalk::scanner::Scanner;
-    use crate::treewalk::token::token_type::TokenType;
-    use crate::treewalk::token::Token;
-    use crate::treewalk::parser::*;
+    use crate::common::scanner::Scanner;
+    use crate::common::token::{Token, Span};
+    use crate::common::parser::*;
+    use crate::common::resolver::*;
+    use crate::common::output::SharedBuffer;
+    use crate::common::LoxError;
     use crate::treewalk::interpreter::*;
+    use crate::treewalk::environment::Environment;
+    use crate::treewalk::object::Object;
+    use crate::treewalk::Interpreter;
+    use crate::repl::Session;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn span(line: u32, column: u32, start: usize, end: usize) -> Span {
+        Span { line, column, start, end }
+    }
+
+    fn interpret_source(source: &str) -> Result<Environment, LoxError> {
+        let mut tokens = Scanner::new(source).scan_tokens()?;
+        let mut statements = parse(&mut tokens).map_err(|mut errors| errors.remove(0))?;
+        resolve(&mut statements).map_err(|mut errors| errors.remove(0))?;
+        let mut environment = Environment::new_root();
+        interpret(statements, &mut environment, false)?;
+        Ok(environment)
+    }
+
+    #[test]
+    fn test_class_initializer_and_method() {
+        let input = "class Foo { init(x) { this.x = x; } bar() { return this.x + 1; } }
+                     var foo = Foo(1);
+                     var result = foo.bar();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::Number(2.0));
+    }
+
+    #[test]
+    fn test_class_set_field() {
+        let input = "class Foo {}
+                     var foo = Foo();
+                     foo.x = \"heya\";
+                     var result = foo.x;";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::String("heya".to_string()));
+    }
+
+    #[test]
+    fn test_class_bound_method() {
+        let input = "class Foo { init() { this.x = 3; } get() { return this.x; } }
+                     var method = Foo().get;
+                     var result = method();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::Number(3.0));
+    }
+
+    #[test]
+    fn test_init_returns_this() {
+        let input = "class Foo { init(x) { this.x = x; if (x > 1) return; this.x = 0; } }
+                     class Bar < Foo { init() { var parent = super.init(5); this.same = parent == this; } }
+                     var foo = Foo(1);
+                     var again = foo.init(2);
+                     var same = again == foo;
+                     var x = foo.x;
+                     var bar = Bar().same;";
+        let environment = interpret_source(input).unwrap();
+        let global = |name: &str| environment.get(&Token::new_identifier(name, 1)).unwrap();
+        assert_eq!(global("same"), Object::Bool(true));
+        assert_eq!(global("x"), Object::Number(2.0));
+        assert_eq!(global("bar"), Object::Bool(true));
+    }
+
+    #[test]
+    fn test_class_undefined_property() {
+        let input = "class Foo {} var foo = Foo(); var result = foo.missing;";
+        assert!(interpret_source(input).is_err());
+    }
+
+    #[test]
+    fn test_inherited_method() {
+        let input = "class A { foo() { return 1; } }
+                     class B < A {}
+                     var result = B().foo();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::Number(1.0));
+    }
+
+    #[test]
+    fn test_super_call_binds_this() {
+        let input = "class A { init(x) { this.x = x; } get() { return this.x; } }
+                     class B < A { init(x) { super.init(x + 1); } get() { return super.get() * 10; } }
+                     var result = B(1).get();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::Number(20.0));
+    }
+
+    #[test]
+    fn test_super_resolves_from_declaring_class() {
+        let input = "class A { name() { return \"A\"; } }
+                     class B < A { name() { return super.name(); } }
+                     class C < B {}
+                     var result = C().name();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::String("A".to_string()));
+    }
+
+    #[test]
+    fn test_inherit_from_non_class() {
+        let input = "var NotAClass = 1; class A < NotAClass {}";
+        let error = interpret_source(input).err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(1, 26, 25, 26), "Superclass must be a class".to_string()));
+    }
+
+    #[test]
+    fn test_this_and_super_resolve_through_closures() {
+        let input = "class A { name() { return \"A\"; } }
+                     class B < A {
+                         names() {
+                             var prefix = \"B\";
+                             fun inner() { { return prefix + super.name() + this.suffix; } }
+                             return inner;
+                         }
+                     }
+                     var b = B();
+                     b.suffix = \"!\";
+                     var result = b.names()();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(environment.get(&Token::new_identifier("result", 1)).unwrap(), Object::String("BA!".to_string()));
+    }
+
+    #[test]
+    fn test_runtime_error_line() {
+        let input = "var a = 1;\nvar b = a + \"heya\";";
+        match interpret_source(input) {
+            Err(LoxError::Runtime(span, _)) => assert_eq!(span.line, 2),
+            _ => panic!("Expected runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_leading_whitespace_keeps_spans() {
+        let errors = super::run(&mut Interpreter::new(), "  \n  print x;", false).err().unwrap();
+        assert_eq!(errors[0].span(), span(2, 9, 11, 12));
+        assert!(errors[0].to_string().starts_with("Line: 2:9 Runtime Error: "));
+    }
+
+    #[test]
+    fn test_leading_blank_lines_count() {
+        let errors = super::run(&mut Interpreter::new(), "\n\nprint x;", false).err().unwrap();
+        assert_eq!(errors[0].line(), 3);
+        let errors = super::run(&mut Interpreter::new(), "\n\nprint 1", false).err().unwrap();
+        assert_eq!(errors[0].line(), 3);
+    }
+
+    #[test]
+    fn test_errors_report_their_column() {
+        let mut tokens = Scanner::new("var a = 1;\nvar b = (a + ;").scan_tokens().unwrap();
+        let error = parse(&mut tokens).err().unwrap().remove(0);
+        assert_eq!((error.line(), error.column()), (2, 14));
+        assert_eq!(error.to_string(), "Line: 2:14 Parse Error: at ';': Expect expression");
+
+        let error = interpret_source("var a = 1;\nvar b = a + \"heya\";").err().unwrap();
+        assert_eq!((error.line(), error.column()), (2, 11));
+        assert!(error.to_string().starts_with("Line: 2:11 Runtime Error: "));
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let input = "print a;";
+        let error = interpret_source(input).err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(1, 7, 6, 7), "Undefined variable 'a'".to_string()));
+    }
+
+    #[test]
+    fn test_closure_is_bound_statically() {
+        let input = "var a = \"global\";
+                     var first;
+                     var second;
+                     {
+                         fun showA() { return a; }
+                         first = showA();
+                         var a = \"block\";
+                         second = showA();
+                     }";
+        let environment = interpret_source(input).unwrap();
+        let global = Object::String("global".to_string());
+        assert_eq!(environment.get(&Token::new_identifier("first", 1)).unwrap(), global);
+        assert_eq!(environment.get(&Token::new_identifier("second", 1)).unwrap(), global);
+    }
+
+    // Runs the source with the natives defined and returns what it printed
+    fn printed(source: &str) -> String {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(buffer.clone()));
+        Session::run(&mut interpreter, source, false).unwrap();
+        buffer.contents()
+    }
+
+    fn global(environment: &Environment, name: &str) -> Object {
+        environment.get(&Token::new_identifier(name, 1)).unwrap()
+    }
+
+    fn string(value: &str) -> Object {
+        Object::String(value.to_string())
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let input = "fun makeCounter() {
+                         var i = 0;
+                         fun count() { i = i + 1; return i; }
+                         return count;
+                     }
+                     var counter = makeCounter();
+                     counter();
+                     var second = counter();
+                     var fresh = makeCounter()();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "second"), Object::Number(2.0));
+        assert_eq!(global(&environment, "fresh"), Object::Number(1.0));
+    }
+
+    #[test]
+    fn test_closures_share_captured_variable() {
+        let input = "var get; var set;
+                     fun make() {
+                         var value = 1;
+                         fun getter() { return value; }
+                         fun setter(v) { value = v; }
+                         get = getter;
+                         set = setter;
+                     }
+                     make();
+                     set(42);
+                     var result = get();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "result"), Object::Number(42.0));
+    }
+
+    #[test]
+    fn test_closure_assign_to_closure() {
+        let input = "var f; var g; var fromF; var fromG;
+                     {
+                         var local = \"local\";
+                         fun fInner() { fromF = local; local = \"after f\"; }
+                         f = fInner;
+                         fun gInner() { fromG = local; local = \"after g\"; }
+                         g = gInner;
+                     }
+                     f();
+                     g();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "fromF"), string("local"));
+        assert_eq!(global(&environment, "fromG"), string("after f"));
+    }
+
+    #[test]
+    fn test_closure_over_parameter() {
+        let input = "var f;
+                     fun foo(param) { fun fInner() { return param; } f = fInner; }
+                     foo(\"param\");
+                     var result = f();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "result"), string("param"));
+    }
+
+    #[test]
+    fn test_nested_closure() {
+        let input = "var f;
+                     fun f1() {
+                         var a = \"a\";
+                         fun f2() {
+                             var b = \"b\";
+                             fun f3() {
+                                 var c = \"c\";
+                                 fun f4() { return a + b + c; }
+                                 f = f4;
+                             }
+                             f3();
+                         }
+                         f2();
+                     }
+                     f1();
+                     var result = f();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "result"), string("abc"));
+    }
+
+    #[test]
+    fn test_shadow_closure_with_local() {
+        let input = "var before; var shadow; var after;
+                     {
+                         var foo = \"closure\";
+                         fun f() {
+                             { before = foo; var foo = \"shadow\"; shadow = foo; }
+                             after = foo;
+                         }
+                         f();
+                     }";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "before"), string("closure"));
+        assert_eq!(global(&environment, "shadow"), string("shadow"));
+        assert_eq!(global(&environment, "after"), string("closure"));
+    }
+
+    #[test]
+    fn test_closure_in_method_sees_this() {
+        let input = "class Foo {
+                         init() { this.x = \"field\"; }
+                         getter() { fun inner() { return this.x; } return inner; }
+                     }
+                     var result = Foo().getter()();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "result"), string("field"));
+    }
+
+    #[test]
+    fn test_interpreter_keeps_globals_between_runs() {
+        let mut interpreter = Interpreter::new();
+        super::run(&mut interpreter, "var a = 1;", true).unwrap();
+        super::run(&mut interpreter, "fun inc(x) { return x + 1; }", true).unwrap();
+        super::run(&mut interpreter, "class Box { get() { return a; } }", true).unwrap();
+        super::run(&mut interpreter, "var result = inc(Box().get());", true).unwrap();
+        assert_eq!(global(interpreter.environment(), "result"), Object::Number(2.0));
+    }
+
+    #[test]
+    fn test_session_bindings_and_reset() {
+        let mut interpreter = Interpreter::new();
+        Session::run(&mut interpreter, "var b = \"two\"; var a = 1;", true).unwrap();
+        let bindings = interpreter.bindings();
+        let clock = ("clock".to_string(), "<native fn clock>".to_string());
+        assert_eq!(bindings[0], ("a".to_string(), "1".to_string()));
+        assert_eq!(bindings[1], ("b".to_string(), "\"two\"".to_string()));
+        assert!(bindings.contains(&clock));
+
+        Session::reset(&mut interpreter);
+        let bindings = interpreter.bindings();
+        assert!(bindings.contains(&clock));
+        assert!(bindings.iter().all(|(_, value)| value.starts_with("<native fn")));
+    }
+
+    #[test]
+    fn test_eval_returns_final_expression() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval("var a = 2; a * 21;").unwrap(), Object::Number(42.0));
+        assert_eq!(interpreter.eval("var b = 1;").unwrap(), Object::Nil);
+        assert_eq!(interpreter.eval("a + b;").unwrap(), Object::Number(3.0));
+    }
+
+    #[test]
+    fn test_eval_reports_errors() {
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.eval("print missing;").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(1, 7, 6, 13), "Undefined variable 'missing'".to_string()));
+        assert!(interpreter.eval("var = 1;").is_err());
+    }
+
+    #[test]
+    fn test_assignment_is_an_expression() {
+        assert_eq!(printed("var a; var b = a = 3; print b; print a = nil;"), "3\nnil\n");
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval("var a; a = 1;").unwrap(), Object::Number(1.0));
+        assert_eq!(interpreter.eval("a = nil;").unwrap(), Object::Nil);
+        assert_eq!(interpreter.eval("var x; fun f() { return x = nil; } f();").unwrap(), Object::Nil);
+        assert_eq!(interpreter.get_global("a"), Some(Object::Nil));
+    }
+
+    #[test]
+    fn test_host_calls_function_and_sets_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("base", Object::Number(10.0));
+        interpreter.eval("fun add(x) { return base + x; } class Point { init(x) { this.x = x; } }").unwrap();
+
+        let result = interpreter.call_function("add", vec![Object::Number(5.0)]).unwrap();
+        assert_eq!(result, Object::Number(15.0));
+        assert!(matches!(interpreter.call_function("Point", vec![Object::Number(1.0)]), Ok(Object::Instance(_))));
+        let error = interpreter.call_function("add", vec![]).err().unwrap();
+        assert_eq!(error.span(), Span::host());
+        assert_eq!(error.to_string(), "Runtime Error: Arguments do not match Parameter arity");
+        assert!(interpreter.call_function("nothing", vec![]).is_err());
+
+        interpreter.set_global("base", Object::Number(0.0));
+        assert_eq!(interpreter.eval("add(1);").unwrap(), Object::Number(1.0));
+        assert_eq!(interpreter.get_global("base"), Some(Object::Number(0.0)));
+        assert_eq!(interpreter.get_global("nothing"), None);
+    }
+
+    #[test]
+    fn test_clock_native() {
+        let mut interpreter = Interpreter::new();
+        let elapsed = interpreter.eval("var start = clock(); clock() - start;").unwrap();
+        assert!(matches!(elapsed, Object::Number(seconds) if seconds >= 0.0));
+        assert!(interpreter.eval("clock(1);").is_err());
+    }
+
+    #[test]
+    fn test_host_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("twice", 1, |arguments| match arguments[0] {
+            Object::Number(number) => Ok(Object::Number(number * 2.0)),
+            _ => Err("twice expects a number".to_string()),
+        });
+        assert_eq!(interpreter.eval("twice(4) + 1;").unwrap(), Object::Number(9.0));
+
+        let error = interpreter.eval("\n twice(\"x\");").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(2, 11, 11, 12), "twice expects a number".to_string()));
+    }
+
+    #[test]
+    fn test_print_goes_to_output() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(buffer.clone()));
+
+        interpreter.eval("fun greet(name) { print \"hi \" + name; } greet(\"lox\"); print 1 + 2;").unwrap();
+        Session::run(&mut interpreter, "true;", true).unwrap();
+        assert_eq!(buffer.contents(), "\"hi lox\"\n3\ntrue\n");
+    }
+
+    #[test]
+    fn test_print_failure_reports_its_line() {
+        struct Closed;
+        impl std::io::Write for Closed {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Closed));
+        let error = interpreter.eval("var a = 1;\n\n print a;").err().unwrap();
+        assert_eq!(error, LoxError::Runtime(span(3, 2, 13, 18), "Couldn't write output: closed".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn test_trace_records_execution() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_trace(Box::new(buffer.clone()));
+        interpreter.eval("fun inc(x) { return x + 1; } var a = 1; { a = inc(a); }").unwrap();
+
+        let trace = buffer.contents();
+        for event in &["statement fun inc", "define a = 1", "push scope 1", "call inc with [1]",
+                       "read x = 1", "return from inc with 2", "write a = 2", "pop scope 1"] {
+            assert!(trace.contains(event), "missing {:?} in trace:\n{}", event, trace);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "trace"))]
+    fn test_trace_is_compiled_out() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_trace(Box::new(buffer.clone()));
+        interpreter.eval("var a = 1; print a;").unwrap();
+        assert_eq!(buffer.contents(), "");
+    }
+
+    #[test]
+    fn test_return_nil_stops_function() {
+        let input = "var after = false;
+                     fun f() { return nil; after = true; }
+                     fun g() { return; after = true; }
+                     var returnedNil = f() == nil;
+                     var returnedBare = g() == nil;";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "after"), Object::Bool(false));
+        assert_eq!(global(&environment, "returnedNil"), Object::Bool(true));
+        assert_eq!(global(&environment, "returnedBare"), Object::Bool(true));
+    }
+
+    #[test]
+    fn test_return_unwinds_nested_loops_and_blocks() {
+        let input = "var steps = 0;
+                     fun find() {
+                         for (var i = 0; i < 10; i = i + 1) {
+                             var j = 0;
+                             while (true) {
+                                 { steps = steps + 1; if (i == 2 and j == 1) return i * 10 + j; }
+                                 j = j + 1;
+                                 if (j == 3) break;
+                             }
+                         }
+                         return -1;
+                     }
+                     fun stop() { while (true) { return nil; } steps = -1; }
+                     var found = find();
+                     stop();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "found"), Object::Number(21.0));
+        assert_eq!(global(&environment, "steps"), Object::Number(8.0));
+    }
+
+    #[test]
+    fn test_break_inside_conditional() {
+        let input = "var n = 0;
+                     while (true) { n = n + 1; if (n == 3) break; }
+                     fun values() { \"ignored\"; 1 + 2; return n; }
+                     var result = values();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "n"), Object::Number(3.0));
+        assert_eq!(global(&environment, "result"), Object::Number(3.0));
+    }
+
+    #[test]
+    fn test_continue_runs_for_increment() {
+        let input = "var sum = 0;
+                     var steps = 0;
+                     for (var i = 0; i < 10; i = i + 1) {
+                         steps = steps + 1;
+                         { if (i == 2 or i == 5) { continue; } }
+                         sum = sum + i;
+                     }
+                     var n = 0;
+                     var small = 0;
+                     while (n < 6) { n = n + 1; if (n > 3) continue; small = small + n; }";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "sum"), Object::Number(38.0));
+        assert_eq!(global(&environment, "steps"), Object::Number(10.0));
+        assert_eq!(global(&environment, "small"), Object::Number(6.0));
+    }
+
+    #[test]
+    fn test_break_and_continue_target_innermost_loop() {
+        let input = "var pairs = 0;
+                     for (var i = 0; i < 3; i = i + 1) {
+                         for (var j = 0; j < 3; j = j + 1) {
+                             if (j == 1) continue;
+                             if (j == 2) { { break; } }
+                             pairs = pairs + 1;
+                         }
+                     }";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "pairs"), Object::Number(3.0));
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let input = "var seen = [];
+                     for (var x in [1, 2]) push(seen, x);
+                     for (var k in {\"a\": 1, 2: nil}) push(seen, k);
+                     for (var c in \"h\u{e9}!\") push(seen, c);
+                     for (var n in range(3, 6)) { if (n == 4) continue; push(seen, n); }
+                     var grown = [1];
+                     for (var x in grown) if (x < 3) push(grown, x + 1);
+                     var closures = [];
+                     for (var i in range(0, 3)) push(closures, fun () { return i; });
+                     var captured = closures[0]() + closures[2]();
+                     fun first(xs) { for (var x in xs) { return x; } return nil; }
+                     var found = first([\"only\"]);";
+        let mut interpreter = Interpreter::new();
+        interpreter.eval(input).unwrap();
+        let seen = vec![Object::Number(1.0), Object::Number(2.0), string("a"), Object::Number(2.0),
+                        string("h"), string("\u{e9}"), string("!"), Object::Number(3.0), Object::Number(5.0)];
+        assert_eq!(interpreter.eval("seen;").unwrap(), list(seen));
+        assert_eq!(interpreter.eval("grown;").unwrap(), numbers(&[1.0, 2.0, 3.0]));
+        assert_eq!(interpreter.eval("captured;").unwrap(), Object::Number(2.0));
+        assert_eq!(interpreter.eval("found;").unwrap(), string("only"));
+    }
+
+    #[test]
+    fn test_for_in_iterator_protocol() {
+        let input = "class Countdown {
+                         init(from) { this.from = from; }
+                         iterator() { return CountdownIterator(this.from); }
+                     }
+                     class CountdownIterator {
+                         init(n) { this.n = n; }
+                         hasNext() { return this.n > 0; }
+                         next() { this.n = this.n - 1; return this.n + 1; }
+                     }
+                     var total = 0;
+                     for (var n in Countdown(4)) {
+                         if (n == 1) break;
+                         total = total * 10 + n;
+                     }";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "total"), Object::Number(432.0));
+    }
+
+    #[test]
+    fn test_for_in_errors() {
+        let error = interpret_source("for (var x in 1) print x;").err().unwrap();
+        assert_eq!(error.message(), "Can only iterate over lists, maps, strings and iterators");
+        let error = interpret_source("class A {} for (var x in A()) print x;").err().unwrap();
+        assert_eq!(error.message(), "Undefined property 'iterator'");
+        let error = interpret_source("for (var x in [1]) {} print x;").err().unwrap();
+        assert_eq!(error.message(), "Undefined variable 'x'");
+    }
+
+    #[test]
+    fn test_lambda_expressions() {
+        let input = "fun apply(f, x) { return f(x); }
+                     var double = fun (n) { return n * 2; };
+                     fun adder(n) { return fun (x) { return x + n; }; }
+                     var doubled = apply(double, 4);
+                     var added = apply(adder(10), 5);
+                     var immediate = fun () { return \"now\"; }();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "doubled"), Object::Number(8.0));
+        assert_eq!(global(&environment, "added"), Object::Number(15.0));
+        assert_eq!(global(&environment, "immediate"), string("now"));
+    }
+
+    #[test]
+    fn test_arrow_functions() {
+        let input = "fun apply(f, x, y) { return f(x, y); }
+                     var a = 3;
+                     var sum = apply((x, y) => x + y, 1, 2);
+                     var curried = (x) => (y) => x * y + a;
+                     var grouped = (a) + 1;
+                     var constant = () => \"lox\";
+                     var result = curried(2)(5);
+                     var called = constant();";
+        let environment = interpret_source(input).unwrap();
+        assert_eq!(global(&environment, "sum"), Object::Number(3.0));
+        assert_eq!(global(&environment, "result"), Object::Number(13.0));
+        assert_eq!(global(&environment, "grouped"), Object::Number(4.0));
+        assert_eq!(global(&environment, "called"), string("lox"));
+    }
+
+    fn list(elements: Vec<Object>) -> Object {
+        Object::List(Rc::new(RefCell::new(elements)))
+    }
+
+    fn numbers(values: &[f64]) -> Object {
+        list(values.iter().map(|value| Object::Number(*value)).collect())
+    }
+
+    #[test]
+    fn test_list_literals_and_indexing() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var xs = [1, \"two\", [3]]; var alias = xs;").unwrap();
+        assert_eq!(interpreter.eval("xs[0];").unwrap(), Object::Number(1.0));
+        assert_eq!(interpreter.eval("xs[-1][0];").unwrap(), Object::Number(3.0));
+        assert_eq!(interpreter.eval("xs[1] = \"deux\";").unwrap(), string("deux"));
+        assert_eq!(interpreter.eval("alias[1];").unwrap(), string("deux"));
+        assert_eq!(interpreter.eval("[];").unwrap(), list(vec![]));
+        assert_eq!(interpreter.eval("xs == alias and xs != [1, \"deux\", [3]];").unwrap(), Object::Bool(true));
+        assert_eq!(format!("{:?}", interpreter.eval("xs;").unwrap()), "[1, \"deux\", [3]]");
+    }
+
+    #[test]
+    fn test_list_slices() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var xs = [0, 1, 2, 3, 4];").unwrap();
+        assert_eq!(interpreter.eval("xs[1:3];").unwrap(), numbers(&[1.0, 2.0]));
+        assert_eq!(interpreter.eval("xs[:2];").unwrap(), numbers(&[0.0, 1.0]));
+        assert_eq!(interpreter.eval("xs[-2:];").unwrap(), numbers(&[3.0, 4.0]));
+        assert_eq!(interpreter.eval("xs[:];").unwrap(), numbers(&[0.0, 1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(interpreter.eval("xs[3:1];").unwrap(), numbers(&[]));
+        interpreter.eval("var copy = xs[:]; copy[0] = 9;").unwrap();
+        assert_eq!(interpreter.eval("xs[0];").unwrap(), Object::Number(0.0));
+    }
+
+    #[test]
+    fn test_list_natives() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var xs = [1, 2]; push(xs, 3); insert(xs, 0, 0); insert(xs, -1, 2.5);").unwrap();
+        assert_eq!(interpreter.eval("xs;").unwrap(), numbers(&[0.0, 1.0, 2.0, 2.5, 3.0]));
+        assert_eq!(interpreter.eval("pop(xs);").unwrap(), Object::Number(3.0));
+        assert_eq!(interpreter.eval("remove(xs, 1);").unwrap(), Object::Number(1.0));
+        assert_eq!(interpreter.eval("len(xs);").unwrap(), Object::Number(3.0));
+        assert_eq!(interpreter.eval("len(\"h\u{e9}llo\");").unwrap(), Object::Number(5.0));
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var xs = [1, 2, 3];").unwrap();
+        let error = |interpreter: &mut Interpreter, source: &str| interpreter.eval(source).err().unwrap();
+        assert_eq!(error(&mut interpreter, "xs[3];"), LoxError::Runtime(span(1, 3, 2, 3), "Index 3 out of bounds for list of length 3".to_string()));
+        assert_eq!(error(&mut interpreter, "\n xs[-4] = 1;").line(), 2);
+        assert_eq!(error(&mut interpreter, "xs[0.5];").message(), "Index 0.5 is not a whole number");
+        assert_eq!(error(&mut interpreter, "xs[\"0\"];").message(), "Index must be a number");
+        assert_eq!(error(&mut interpreter, "xs[1:9];").message(), "Index 9 out of bounds for list of length 3");
+        assert_eq!(error(&mut interpreter, "1[0];").message(), "Only lists and maps can be indexed");
+        assert_eq!(error(&mut interpreter, "pop([]);").message(), "Can't pop from an empty list");
+        assert_eq!(error(&mut interpreter, "remove(xs, 3);").message(), "Index 3 out of bounds for list of length 3");
+        assert_eq!(error(&mut interpreter, "push(1, 2);").message(), "push() expects a list");
+    }
+
+    #[test]
+    fn test_map_literals_and_subscripts() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var m = {\"a\": 1, 2: \"two\", true: [3], nil: 0}; var alias = m;").unwrap();
+        assert_eq!(interpreter.eval("m[\"a\"];").unwrap(), Object::Number(1.0));
+        assert_eq!(interpreter.eval("m[1 + 1];").unwrap(), string("two"));
+        assert_eq!(interpreter.eval("m[true][0];").unwrap(), Object::Number(3.0));
+        assert_eq!(interpreter.eval("m[nil];").unwrap(), Object::Number(0.0));
+        assert_eq!(interpreter.eval("m[-0] = \"zero\";").unwrap(), string("zero"));
+        assert_eq!(interpreter.eval("alias[0];").unwrap(), string("zero"));
+        assert_eq!(interpreter.eval("m[\"a\"] = 5;").unwrap(), Object::Number(5.0));
+        assert_eq!(interpreter.eval("m == alias and m != {};").unwrap(), Object::Bool(true));
+        assert_eq!(format!("{:?}", interpreter.eval("m;").unwrap()),
+                   "{\"a\": 5, 2: \"two\", true: [3], nil: 0, 0: \"zero\"}");
+        assert_eq!(format!("{:?}", interpreter.eval("[{}];").unwrap()), "[{}]");
+    }
+
+    #[test]
+    fn test_map_natives() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var m = {\"b\": 1, \"a\": 2, \"c\": 3};").unwrap();
+        assert_eq!(interpreter.eval("delete(m, \"a\");").unwrap(), Object::Number(2.0));
+        interpreter.eval("m[\"a\"] = 4;").unwrap();
+        assert_eq!(interpreter.eval("keys(m);").unwrap(), list(vec![string("b"), string("c"), string("a")]));
+        assert_eq!(interpreter.eval("values(m);").unwrap(), numbers(&[1.0, 3.0, 4.0]));
+        assert_eq!(interpreter.eval("has(m, \"c\") and !has(m, 1);").unwrap(), Object::Bool(true));
+        assert_eq!(interpreter.eval("len(m);").unwrap(), Object::Number(3.0));
+
+        let source = "var total = 0; var ks = keys(m);
+                      for (var i = 0; i < len(ks); i = i + 1) total = total + m[ks[i]];
+                      total;";
+        assert_eq!(interpreter.eval(source).unwrap(), Object::Number(8.0));
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var m = {\"a\": 1};").unwrap();
+        let error = |interpreter: &mut Interpreter, source: &str| interpreter.eval(source).err().unwrap();
+        assert_eq!(error(&mut interpreter, "m[\"b\"];"), LoxError::Runtime(span(1, 2, 1, 2), "Undefined key \"b\"".to_string()));
+        assert_eq!(error(&mut interpreter, "m[[]] = 1;").message(), "Map keys must be nil, booleans, numbers or strings");
+        assert_eq!(error(&mut interpreter, "\nvar n = {m: 1};").line(), 2);
+        assert_eq!(error(&mut interpreter, "m[0/0];").message(), "NaN can't be used as a map key");
+        assert_eq!(error(&mut interpreter, "m[0:1];").message(), "Only lists can be sliced");
+        assert_eq!(error(&mut interpreter, "delete(m, \"b\");").message(), "Undefined key \"b\"");
+        assert_eq!(error(&mut interpreter, "keys([]);").message(), "keys() expects a map");
+
+        let mut tokens = Scanner::new("var m = {\"a\" 1};").scan_tokens().unwrap();
+        assert!(parse(&mut tokens).err().unwrap()[0].message().ends_with("Expect ':' after map key"));
+    }
+
+    // One expression for each kind of value, all distinct from each other
+    const SAMPLE_SETUP: &str = "fun f() {} fun g(x) { return x; } class A { m() {} } class B {} \
+                                var a = A(); var b = B(); var m = a.m; var xs = []; var ys = [1]; var ms = {};";
+    const SAMPLES: [&str; 18] = ["nil", "true", "false", "0", "1", "\"\"", "\"lox\"", "f", "g",
+                                 "clock", "A", "B", "a", "b", "m", "xs", "ys", "ms"];
+
+    #[test]
+    fn test_returned_nil_can_be_stored() {
+        assert_eq!(printed("fun f() { return nil; } var r = f(); print r;"), "nil\n");
+        assert_eq!(printed("fun g() { return; } var r = g(); print r;"), "nil\n");
+        assert_eq!(printed("class A { m() { return nil; } } var r = A().m(); print r;"), "nil\n");
+    }
+
+    #[test]
+    fn test_nil_in_lists_and_maps() {
+        assert_eq!(printed("var xs = [nil, 1]; print xs; print xs[0];"), "[nil, 1]\nnil\n");
+        assert_eq!(printed("var xs = [1]; xs[0] = nil; push(xs, nil); insert(xs, 0, nil); print xs;"), "[nil, nil, nil]\n");
+        assert_eq!(printed("var m = {\"a\": nil}; m[\"b\"] = nil; print m; print values(m);"), "{\"a\": nil, \"b\": nil}\n[nil, nil]\n");
+    }
+
+    #[test]
+    fn test_nil_in_variables() {
+        assert_eq!(printed("var x = nil; print x; var y; print y == x; x = 1; x = nil; print x;"), "nil\ntrue\nnil\n");
+        assert_eq!(printed("fun f(a) { var b = a; return b; } print f(nil);"), "nil\n");
+        assert_eq!(printed("var x = nil; print !x; if (x) print 1; else print 2;"), "true\n2\n");
+    }
+
+    #[test]
+    fn test_truthiness_of_every_value() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval(SAMPLE_SETUP).unwrap();
+
+        for sample in SAMPLES.iter() {
+            let truthy = !matches!(*sample, "nil" | "false");
+            let negated = interpreter.eval(&format!("!{};", sample)).unwrap();
+            assert_eq!(negated, Object::Bool(!truthy), "!{}", sample);
+
+            let branch = interpreter.eval(&format!("var r = \"else\"; if ({}) r = \"then\"; r;", sample)).unwrap();
+            assert_eq!(branch, string(if truthy { "then" } else { "else" }), "if ({})", sample);
+
+            let looped = interpreter.eval(&format!("var n = 0; while ({} and n < 1) n = n + 1; n;", sample)).unwrap();
+            assert_eq!(looped, Object::Number(if truthy { 1.0 } else { 0.0 }), "while ({})", sample);
+        }
+    }
+
+    #[test]
+    fn test_equality_of_every_pair_of_values() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval(SAMPLE_SETUP).unwrap();
+
+        for (i, left) in SAMPLES.iter().enumerate() {
+            for (j, right) in SAMPLES.iter().enumerate() {
+                let equal = interpreter.eval(&format!("{} == {};", left, right)).unwrap();
+                assert_eq!(equal, Object::Bool(i == j), "{} == {}", left, right);
+                let not_equal = interpreter.eval(&format!("{} != {};", left, right)).unwrap();
+                assert_eq!(not_equal, Object::Bool(i != j), "{} != {}", left, right);
+            }
+        }
+    }
 
     #[test]
-    fn test_eof() {
-        let input = "";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new(TokenType::Eof, "", 1));
+    fn test_equality_by_value_and_identity() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("class A {} var a = A(); var same = a;").unwrap();
+        assert_eq!(interpreter.eval("a == same;").unwrap(), Object::Bool(true));
+        assert_eq!(interpreter.eval("a == A();").unwrap(), Object::Bool(false));
+        assert_eq!(interpreter.eval("\"lo\" + \"x\" == \"lox\";").unwrap(), Object::Bool(true));
+        assert_eq!(interpreter.eval("1 + 1 == 2;").unwrap(), Object::Bool(true));
     }
 
     #[test]
-    fn test_single_char() {
-        let input = "=";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new(TokenType::Equal, "", 1));
+    fn test_functions_are_equal_only_to_themselves() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var f = (x) => x; var g = (x) => x; var h = f;
+                          fun same() {} var before = same; fun same() {}
+                          var made = []; for (var i = 0; i < 2; i = i + 1) push(made, () => 1);
+                          class A { m() {} n() {} } var a = A();").unwrap();
+
+        assert_eq!(interpreter.eval("f == h;").unwrap(), Object::Bool(true));
+        assert_eq!(interpreter.eval("f == g;").unwrap(), Object::Bool(false));
+        assert_eq!(interpreter.eval("before == same;").unwrap(), Object::Bool(false));
+        assert_eq!(interpreter.eval("made[0] == made[1];").unwrap(), Object::Bool(false));
+        assert_eq!(interpreter.eval("a.m == a.m;").unwrap(), Object::Bool(true));
+        assert_eq!(interpreter.eval("a.m == a.n;").unwrap(), Object::Bool(false));
+        assert_eq!(interpreter.eval("a.m == A().m;").unwrap(), Object::Bool(false));
     }
 
     #[test]
-    fn test_double_char() {
-        let input = "==";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new(TokenType::EqualEqual, "", 1));
+    fn test_collector_frees_closure_cycles() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("fun outer() { fun inner() { return inner; } return inner; }").unwrap();
+        let scope = match interpreter.eval("outer();").unwrap() {
+            Object::Function(_, _, closure) => Rc::downgrade(closure.enclosure_stack.last().unwrap()),
+            other => panic!("Expected a function, got {:?}", other),
+        };
+
+        // `inner` is stored in the scope it captures, so dropping it isn't enough
+        assert!(scope.upgrade().is_some());
+        assert!(interpreter.collect_garbage() > 0);
+        assert!(scope.upgrade().is_none());
     }
 
     #[test]
-    fn test_multi_char() {
-        let input = "= !=";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new(TokenType::Equal, "", 1));
-        assert_eq!(tok[1], Token::new(TokenType::BangEqual, "", 1));
+    fn test_collector_frees_instance_cycles() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("class Node {} var kept = Node(); kept.me = kept; var dropped = Node(); dropped.me = dropped;").unwrap();
+        let dropped = match interpreter.get_global("dropped") {
+            Some(Object::Instance(instance)) => Rc::downgrade(&instance),
+            other => panic!("Expected an instance, got {:?}", other),
+        };
+        interpreter.eval("dropped = nil;").unwrap();
+
+        assert!(dropped.upgrade().is_some());
+        interpreter.collect_garbage();
+        assert!(dropped.upgrade().is_none());
+        assert_eq!(interpreter.eval("kept.me == kept;").unwrap(), Object::Bool(true));
     }
 
     #[test]
-    fn test_number() {
-        let input = "1";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new_number(1.0, 1));
+    fn test_collector_keeps_reachable_closures() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("fun makeCounter() { var i = 0; fun count() { i = i + 1; return i; } return count; }
+                          var counter = makeCounter(); counter();").unwrap();
+        interpreter.collect_garbage();
+        assert_eq!(interpreter.eval("counter();").unwrap(), Object::Number(2.0));
     }
 
     #[test]
-    fn test_number_decimal() {
-        let input = "1.23";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new_number(1.23, 1));
+    fn test_collector_keeps_values_the_host_holds() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("fun makeCounter() { var i = 0; fun count() { i = i + 1; return i; } return count; }
+                          class Node {} fun cycle() { var node = Node(); node.me = node; return node; }").unwrap();
+        let counter = interpreter.eval("makeCounter();").unwrap();
+        let node = interpreter.eval("cycle();").unwrap();
+        interpreter.eval("makeCounter(); cycle();").unwrap();
+
+        assert!(interpreter.collect_garbage() > 0);
+        interpreter.set_global("counter", counter);
+        interpreter.set_global("node", node);
+        assert_eq!(interpreter.eval("counter(); counter();").unwrap(), Object::Number(2.0));
+        assert_eq!(interpreter.eval("node.me == node;").unwrap(), Object::Bool(true));
     }
 
     #[test]
-    fn test_string() {
-        let input = "\"heya\"";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new_string("heya", 1));
+    fn test_collector_frees_cycles_through_lists_and_maps() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("class Box {} var box = Box(); box.items = [box]; box.index = {}; box.index[\"box\"] = box;").unwrap();
+        let (list, map) = match (interpreter.eval("box.items;").unwrap(), interpreter.eval("box.index;").unwrap()) {
+            (Object::List(list), Object::Map(map)) => (Rc::downgrade(&list), Rc::downgrade(&map)),
+            other => panic!("Expected a list and a map, got {:?}", other),
+        };
+        interpreter.eval("box = nil;").unwrap();
+
+        interpreter.collect_garbage();
+        assert!(list.upgrade().is_none());
+        assert!(map.upgrade().is_none());
     }
 
     #[test]
-    fn test_keyword() {
-        let input = "and or while";
-        let scanner = Scanner::new(input);
-        let tok = scanner.scan_tokens();
-        assert_eq!(tok[0], Token::new_keyword(TokenType::And, 1));
-        assert_eq!(tok[1], Token::new_keyword(TokenType::Or, 1));
-        assert_eq!(tok[2], Token::new_keyword(TokenType::While, 1));
+    fn test_collector_growth_factor() {
+        // Keeps 2000 instances alive, so the first collection puts the next one 2000 times
+        // the growth factor scopes away
+        let leaked_scope_survives = |growth_factor: usize| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_gc_growth_factor(growth_factor);
+            interpreter.run("class Node {} var kept = []; for (var i = 0; i < 2000; i = i + 1) push(kept, Node());", true).unwrap();
+            interpreter.run("fun leak() { fun inner() { return inner; } return inner; }", true).unwrap();
+            let scope = match interpreter.eval("leak();").unwrap() {
+                Object::Function(_, _, closure) => Rc::downgrade(closure.enclosure_stack.last().unwrap()),
+                other => panic!("Expected a function, got {:?}", other),
+            };
+            interpreter.run("for (var i = 0; i < 2500; i = i + 1) leak();", true).unwrap();
+            scope.upgrade().is_some()
+        };
+
+        assert!(!leaked_scope_survives(1));
+        assert!(leaked_scope_survives(10));
     }
 }
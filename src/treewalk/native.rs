@@ -0,0 +1,142 @@
+use crate::common::list;
+use crate::treewalk::object::{Object, ObjectMap};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type NativeFn = dyn Fn(&[Object]) -> Result<Object, String>;
+
+// A function implemented in Rust. Errors are plain messages; the interpreter attaches the
+// line of the call that failed.
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl Native {
+    pub fn new(name: &str, arity: usize, function: Box<NativeFn>) -> Native {
+        let name = name.to_string();
+        Native { name, arity, function }
+    }
+
+    pub fn call(&self, arguments: &[Object]) -> Result<Object, String> {
+        (self.function)(arguments)
+    }
+}
+
+// Seconds since the Unix epoch
+pub fn clock(_arguments: &[Object]) -> Result<Object, String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    Ok(Object::Number(now.as_secs_f64()))
+}
+
+// The number of elements in a list, of entries in a map, or of characters in a string
+pub fn len(arguments: &[Object]) -> Result<Object, String> {
+    match &arguments[0] {
+        Object::List(list) => Ok(Object::Number(list.borrow().len() as f64)),
+        Object::Map(map) => Ok(Object::Number(map.borrow().len() as f64)),
+        Object::String(string) => Ok(Object::Number(string.chars().count() as f64)),
+        _ => Err("len() expects a list, a map or a string".to_string()),
+    }
+}
+
+pub fn push(arguments: &[Object]) -> Result<Object, String> {
+    expect_list("push", &arguments[0])?.borrow_mut().push(arguments[1].clone());
+    Ok(Object::Nil)
+}
+
+pub fn pop(arguments: &[Object]) -> Result<Object, String> {
+    expect_list("pop", &arguments[0])?.borrow_mut().pop()
+        .ok_or_else(|| "Can't pop from an empty list".to_string())
+}
+
+// Inserts before the element at the index; the list's length appends
+pub fn insert(arguments: &[Object]) -> Result<Object, String> {
+    let list = expect_list("insert", &arguments[0])?;
+    let position = list::boundary(expect_index("insert", &arguments[1])?, list.borrow().len())?;
+    list.borrow_mut().insert(position, arguments[2].clone());
+    Ok(Object::Nil)
+}
+
+// Returns the element that was removed
+pub fn remove(arguments: &[Object]) -> Result<Object, String> {
+    let list = expect_list("remove", &arguments[0])?;
+    let position = list::element(expect_index("remove", &arguments[1])?, list.borrow().len())?;
+    let removed = list.borrow_mut().remove(position);
+    Ok(removed)
+}
+
+// Keys and values come out in the order the keys were first added
+pub fn keys(arguments: &[Object]) -> Result<Object, String> {
+    let keys = expect_map("keys", &arguments[0])?.borrow().keys().map(Object::from).collect();
+    Ok(Object::List(Rc::new(RefCell::new(keys))))
+}
+
+pub fn values(arguments: &[Object]) -> Result<Object, String> {
+    let values = expect_map("values", &arguments[0])?.borrow().values().cloned().collect();
+    Ok(Object::List(Rc::new(RefCell::new(values))))
+}
+
+pub fn has(arguments: &[Object]) -> Result<Object, String> {
+    let map = expect_map("has", &arguments[0])?;
+    Ok(Object::Bool(map.borrow().contains(&arguments[1].to_key()?)))
+}
+
+// Returns the value that was removed
+pub fn delete(arguments: &[Object]) -> Result<Object, String> {
+    let map = expect_map("delete", &arguments[0])?;
+    let removed = map.borrow_mut().remove(&arguments[1].to_key()?);
+    removed.ok_or_else(|| format!("Undefined key {:?}", arguments[1]))
+}
+
+pub fn range(arguments: &[Object]) -> Result<Object, String> {
+    match (&arguments[0], &arguments[1]) {
+        (Object::Number(start), Object::Number(end)) => {
+            let numbers = list::range(*start, *end)?.into_iter().map(Object::Number).collect();
+            Ok(Object::List(Rc::new(RefCell::new(numbers))))
+        }
+        _ => Err("range() expects numbers".to_string()),
+    }
+}
+
+fn expect_list<'a>(name: &str, argument: &'a Object) -> Result<&'a Rc<RefCell<Vec<Object>>>, String> {
+    match argument {
+        Object::List(list) => Ok(list),
+        _ => Err(format!("{}() expects a list", name)),
+    }
+}
+
+fn expect_map<'a>(name: &str, argument: &'a Object) -> Result<&'a Rc<RefCell<ObjectMap>>, String> {
+    match argument {
+        Object::Map(map) => Ok(map),
+        _ => Err(format!("{}() expects a map", name)),
+    }
+}
+
+fn expect_index(name: &str, argument: &Object) -> Result<f64, String> {
+    match argument {
+        Object::Number(index) => Ok(*index),
+        _ => Err(format!("{}() expects a number index", name)),
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Native {
+    fn partial_cmp(&self, _other: &Native) -> Option<Ordering> {
+        None
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::common::token::{Literal, Token};
+use crate::common::statement::Stmt;
+use crate::common::map::{self, Key, Map};
+use crate::treewalk::environment::Environment;
+use crate::treewalk::class::{Class, Instance};
+use crate::treewalk::native::Native;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub type ObjectMap = Map<Key<String>, Object>;
+
+#[derive(PartialOrd, PartialEq, Clone)]
+pub enum Object {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    // The body is shared by every copy of one function value, which gives it an identity
+    Function(Vec<Token>, Rc<Vec<Stmt>>, Environment),
+    Native(Rc<Native>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<RefCell<Instance>>, Box<Object>, Option<Rc<Class>>),
+    // Shared, so changes made through one reference are seen through every other
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<ObjectMap>>),
+    Nil,
+}
+
+impl std::fmt::Debug for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Object::String(s) => write!(f, "\"{}\"", s),
+            Object::Number(n) => write!(f, "{}", n),
+            Object::Bool(true) => write!(f, "true"),
+            Object::Bool(false) => write!(f, "false"),
+            Object::Nil => write!(f, "nil"),
+            // The closure is left out: it usually contains this function
+            Object::Function(parameters, block, _) => {
+                write!(f, "Function: {:?} {:?}", parameters, block)
+            },
+            Object::Native(native) => write!(f, "{:?}", native),
+            Object::Class(class) => write!(f, "{:?}", class),
+            Object::Instance(instance) => write!(f, "{:?}", instance.borrow()),
+            Object::BoundMethod(instance, method, _) => {
+                write!(f, "BoundMethod: {:?} {:?}", instance.borrow(), method)
+            },
+            Object::List(list) => {
+                let elements: Vec<String> = list.borrow().iter().map(|element| format!("{:?}", element)).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+            Object::Map(map) => {
+                let entries: Vec<String> = map.borrow().iter()
+                    .map(|(key, value)| format!("{:?}: {:?}", Object::from(key), value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            },
+        }
+    }
+}
+
+impl Object {
+    pub fn to_key(&self) -> Result<Key<String>, String> {
+        match self {
+            Object::Nil => Ok(Key::Nil),
+            Object::Bool(value) => Ok(Key::Bool(*value)),
+            Object::Number(number) => Key::number(*number),
+            Object::String(string) => Ok(Key::String(string.clone())),
+            _ => Err(map::KEY_ERROR.to_string()),
+        }
+    }
+}
+
+impl From<&Key<String>> for Object {
+    fn from(key: &Key<String>) -> Object {
+        match key {
+            Key::Nil => Object::Nil,
+            Key::Bool(value) => Object::Bool(*value),
+            Key::Number(bits) => Object::Number(map::key_number(*bits)),
+            Key::String(string) => Object::String(string.clone()),
+        }
+    }
+}
+
+// A token without a literal never makes it into a literal expression
+impl From<Literal> for Object {
+    fn from(literal: Literal) -> Object {
+        match literal {
+            Literal::String(string) => Object::String(string),
+            Literal::Number(number) => Object::Number(number),
+            Literal::Bool(value) => Object::Bool(value),
+            Literal::None | Literal::Nil => Object::Nil,
+        }
+    }
+}